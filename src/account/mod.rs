@@ -0,0 +1,231 @@
+use pbkdf2::pbkdf2_hmac;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use subtle::ConstantTimeEq;
+
+// File that stores every account. Each line is the name, salt, password hash and banned flag separated by tabs.
+pub const ACCOUNT_PATH: &str = "accounts.txt";
+
+// Password hashes start with the scheme and its work factor, such as "pbkdf2-sha256$100000$<hash>", so older hashes
+// can be recognized and upgraded when the player next logs in. Hashes without a scheme are a single SHA-256 of the
+// salt followed by the password.
+const PBKDF2_SCHEME: &str = "pbkdf2-sha256";
+const PBKDF2_ROUNDS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+#[derive(Clone, Debug)]
+pub struct Account {
+    pub name: String,
+    // Hex encoded salt and password hash.
+    salt: String,
+    password_hash: String,
+    pub banned: bool,
+}
+
+impl Account {
+    pub fn check_password(&self, password: &str) -> bool {
+        let expected = match self.password_hash.split_once('$') {
+            Some((PBKDF2_SCHEME, rest)) => {
+                let Some((rounds, _)) = rest.split_once('$') else {
+                    return false;
+                };
+
+                let Ok(rounds) = rounds.parse() else {
+                    return false;
+                };
+
+                pbkdf2_hash(&self.salt, password, rounds)
+            }
+            Some(_) => return false,
+            None => sha256_hash(&self.salt, password),
+        };
+
+        expected
+            .as_bytes()
+            .ct_eq(self.password_hash.as_bytes())
+            .into()
+    }
+
+    // The hash uses an old scheme or fewer rounds than new hashes get.
+    fn needs_rehash(&self) -> bool {
+        let prefix = format!("{}${}$", PBKDF2_SCHEME, PBKDF2_ROUNDS);
+
+        !self.password_hash.starts_with(&prefix)
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+
+        Some(Self {
+            name: fields.next()?.to_owned(),
+            salt: fields.next()?.to_owned(),
+            password_hash: fields.next()?.to_owned(),
+            banned: fields.next().is_some_and(|banned| banned.trim() == "1"),
+        })
+    }
+
+    fn write(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\n",
+            self.name, self.salt, self.password_hash, self.banned as u8
+        )
+    }
+}
+
+// Accounts stored by lowercase name.
+pub struct AccountStore {
+    path: String,
+    accounts: HashMap<String, Account>,
+}
+
+impl AccountStore {
+    pub fn load(path: &str) -> Self {
+        let mut accounts = HashMap::new();
+
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+                    match Account::parse(line) {
+                        Some(account) => {
+                            accounts.insert(account.name.to_lowercase(), account);
+                        }
+                        None => println!("Ignoring malformed account line in {}", path),
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => println!("Failed to read accounts from {}: {}", path, e),
+        }
+
+        Self {
+            path: path.to_owned(),
+            accounts,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Account> {
+        self.accounts.get(&name.to_lowercase())
+    }
+
+    // Registers a new account with a fresh salt and saves the store. Returns false if no salt could be generated or the
+    // store couldn't be saved, in which case the account isn't created.
+    pub fn create(&mut self, name: &str, password: &str) -> bool {
+        let Some(salt) = generate_salt() else {
+            return false;
+        };
+
+        let account = Account {
+            name: name.to_owned(),
+            password_hash: pbkdf2_hash(&salt, password, PBKDF2_ROUNDS),
+            salt,
+            banned: false,
+        };
+
+        self.accounts.insert(name.to_lowercase(), account);
+
+        if !self.save() {
+            self.accounts.remove(&name.to_lowercase());
+            return false;
+        }
+
+        true
+    }
+
+    // Rehashes the password of an account with an old hash. The password must already have been checked.
+    pub fn upgrade_hash(&mut self, name: &str, password: &str) {
+        let Some(account) = self.accounts.get_mut(&name.to_lowercase()) else {
+            return;
+        };
+
+        if !account.needs_rehash() {
+            return;
+        }
+
+        let Some(salt) = generate_salt() else {
+            return;
+        };
+
+        account.password_hash = pbkdf2_hash(&salt, password, PBKDF2_ROUNDS);
+        account.salt = salt;
+
+        if self.save() {
+            println!("Upgraded the password hash of {}", name);
+        }
+    }
+
+    fn save(&self) -> bool {
+        let mut accounts: Vec<&Account> = self.accounts.values().collect();
+        accounts.sort_by_key(|account| account.name.to_lowercase());
+
+        let contents: String = accounts.iter().map(|account| account.write()).collect();
+
+        if let Err(e) = std::fs::write(&self.path, contents) {
+            println!("Failed to write accounts to {}: {}", self.path, e);
+            return false;
+        }
+
+        true
+    }
+}
+
+fn pbkdf2_hash(salt: &str, password: &str, rounds: u32) -> String {
+    let mut hash = [0; HASH_LEN];
+
+    pbkdf2_hmac::<Sha256>(password.as_bytes(), salt.as_bytes(), rounds, &mut hash);
+
+    format!("{}${}${}", PBKDF2_SCHEME, rounds, to_hex(&hash))
+}
+
+fn sha256_hash(salt: &str, password: &str) -> String {
+    let mut hasher = Sha256::new();
+
+    hasher.update(salt.as_bytes());
+    hasher.update(password.as_bytes());
+
+    to_hex(&hasher.finalize())
+}
+
+fn generate_salt() -> Option<String> {
+    let mut salt = [0; SALT_LEN];
+
+    if let Err(e) = getrandom::getrandom(&mut salt) {
+        println!("Failed to generate a salt: {}", e);
+        return None;
+    }
+
+    Some(to_hex(&salt))
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(salt: &str, password_hash: String) -> Account {
+        Account {
+            name: "Player".to_owned(),
+            salt: salt.to_owned(),
+            password_hash,
+            banned: false,
+        }
+    }
+
+    #[test]
+    fn checks_current_and_legacy_hashes() {
+        let current = account("abcd", pbkdf2_hash("abcd", "secret", PBKDF2_ROUNDS));
+
+        assert!(current.check_password("secret"));
+        assert!(!current.check_password("Secret"));
+        assert!(!current.needs_rehash());
+
+        let legacy = account("abcd", sha256_hash("abcd", "secret"));
+
+        assert!(legacy.check_password("secret"));
+        assert!(!legacy.check_password("wrong"));
+        assert!(legacy.needs_rehash());
+    }
+}
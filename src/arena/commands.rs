@@ -0,0 +1,175 @@
+use crate::command::{Command, CommandContext, CommandRegistry, CommandTarget, TargetKind};
+use crate::player::{PrizeTarget, SHIP_SPECTATOR};
+use crate::{Connection, Game};
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+
+// Commands for arenas and the players in them.
+pub fn register(commands: &mut CommandRegistry) {
+    commands.register(Command {
+        name: "arena",
+        usage: "",
+        help: "Lists the arenas and how many players are in each.",
+        capability: None,
+        staff: false,
+        targets: &[TargetKind::Arena],
+        handler: command_arena,
+    });
+
+    commands.register(Command {
+        name: "setship",
+        usage: "<1-9>",
+        help: "Puts the player in the ship. Ship 9 is spectator mode.",
+        capability: Some("cmd_setship"),
+        staff: false,
+        targets: &[TargetKind::Player],
+        handler: command_setship,
+    });
+
+    commands.register(Command {
+        name: "setfreq",
+        usage: "<freq>",
+        help: "Moves the player to the freq.",
+        capability: Some("cmd_setfreq"),
+        staff: false,
+        targets: &[TargetKind::Player],
+        handler: command_setfreq,
+    });
+
+    commands.register(Command {
+        name: "prize",
+        usage: "<prize> [count]",
+        help: "Gives the prize to the player or freq. A negative prize takes it away.",
+        capability: Some("cmd_prize"),
+        staff: false,
+        targets: &[TargetKind::Player, TargetKind::Freq],
+        handler: command_prize,
+    });
+}
+
+fn command_arena(
+    game: &mut Game,
+    game_socket: &UdpSocket,
+    connections: &mut HashMap<SocketAddr, Connection>,
+    context: &CommandContext,
+) {
+    if let Some(player) = game.player_manager.players.get(&context.sender) {
+        let addr = player.addr;
+        game.send_arena_list(game_socket, connections, addr);
+    }
+}
+
+// *setship <ship> puts the targeted player in the ship, numbered 1 to 8 with 9 being spectator mode. The arena's
+// ship and freq limits don't apply.
+fn command_setship(
+    game: &mut Game,
+    game_socket: &UdpSocket,
+    connections: &mut HashMap<SocketAddr, Connection>,
+    context: &CommandContext,
+) {
+    let CommandTarget::Player(target) = context.target else {
+        return;
+    };
+
+    let ship = match context.parse_arg::<u8>(0) {
+        Some(ship) if (1..=SHIP_SPECTATOR + 1).contains(&ship) => ship - 1,
+        _ => {
+            let usage = "Usage: *setship <1-9>";
+            game.send_arena_message(game_socket, connections, context.sender, usage);
+            return;
+        }
+    };
+
+    let Some(player) = game.player_manager.players.get(&target) else {
+        return;
+    };
+
+    let Some(arena_name) = player.arena.clone() else {
+        return;
+    };
+
+    let Some(spectator_freq) = game
+        .arena_manager
+        .arenas
+        .get(&arena_name)
+        .map(|arena| arena.config.spectator_freq)
+    else {
+        return;
+    };
+
+    let freq = match (ship, player.freq) {
+        (SHIP_SPECTATOR, _) => spectator_freq,
+        (_, freq) if freq == spectator_freq => game.balanced_freq(&arena_name).unwrap_or(0),
+        (_, freq) => freq,
+    };
+
+    game.set_ship_and_freq(game_socket, connections, target, ship, freq);
+}
+
+// *setfreq <freq> moves the targeted player to the freq without checking the arena's freq limits.
+fn command_setfreq(
+    game: &mut Game,
+    game_socket: &UdpSocket,
+    connections: &mut HashMap<SocketAddr, Connection>,
+    context: &CommandContext,
+) {
+    let CommandTarget::Player(target) = context.target else {
+        return;
+    };
+
+    let Some(freq) = context.parse_arg::<u16>(0) else {
+        game.send_arena_message(
+            game_socket,
+            connections,
+            context.sender,
+            "Usage: *setfreq <freq>",
+        );
+        return;
+    };
+
+    let Some(player) = game.player_manager.players.get(&target) else {
+        return;
+    };
+
+    match player.ship {
+        SHIP_SPECTATOR => game.set_freq(game_socket, connections, target, freq),
+        ship => game.set_ship_and_freq(game_socket, connections, target, ship, freq),
+    }
+}
+
+// *prize <prize> [count] gives the prize to the targeted player or freq.
+fn command_prize(
+    game: &mut Game,
+    game_socket: &UdpSocket,
+    connections: &mut HashMap<SocketAddr, Connection>,
+    context: &CommandContext,
+) {
+    let Some(prize) = context.parse_arg::<i16>(0) else {
+        game.send_arena_message(
+            game_socket,
+            connections,
+            context.sender,
+            "Usage: *prize <prize> [count]",
+        );
+        return;
+    };
+
+    let count = context.parse_arg::<i16>(1).unwrap_or(1);
+
+    let Some(arena) = game
+        .player_manager
+        .players
+        .get(&context.sender)
+        .and_then(|player| player.arena.clone())
+    else {
+        return;
+    };
+
+    let target = match context.target {
+        CommandTarget::Player(pid) => PrizeTarget::Player(pid),
+        CommandTarget::Freq(freq) => PrizeTarget::Freq(freq),
+        CommandTarget::Arena => return,
+    };
+
+    game.grant_prize(game_socket, connections, &arena, target, prize, count);
+}
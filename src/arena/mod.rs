@@ -1,3 +1,18 @@
+use crate::arena::settings::{ArenaSettings, SHIP_COUNT};
+use crate::clock::{TICKS_PER_SECOND, Tick};
+use crate::config::Config;
+use crate::map::door::{Doors, VieRng};
+use crate::map::{
+    LevelFile, MAP_DIRECTORY, MAX_MAP_FILENAME_LEN, Map, STOCK_MAP_CHECKSUM, STOCK_MAP_FILENAME,
+    STOCK_MAP_FILESIZE,
+};
+use crate::packet::{MAX_PACKET_SIZE, Packet};
+use crate::player::{SHIP_SPECTATOR, Score};
+use std::collections::HashMap;
+
+pub mod commands;
+pub mod settings;
+
 // TODO: Read and serialize
 pub const ARENA_SETTINGS: [u8; 1428] = [
     15, 1, 7, 0, 112, 23, 0, 0, 160, 15, 0, 0, 220, 5, 100, 0, 20, 0, 30, 0, 44, 1, 50, 0, 14, 1,
@@ -56,3 +71,538 @@ pub const ARENA_SETTINGS: [u8; 1428] = [
     0, 0, 0, 0, 0, 0, 0, 0, 70, 90, 50, 40, 30, 20, 5, 60, 60, 40, 80, 70, 60, 3, 30, 40, 5, 2, 60,
     10, 40, 5, 10, 15, 20, 10, 10, 30,
 ];
+
+pub const DEFAULT_PUBLIC_ARENA_CAP: usize = 200;
+pub const DEFAULT_MAP: &str = "pub.lvl";
+// Position tiers as radius in pixels and the minimum ticks between position packets within that radius.
+pub const DEFAULT_POSITION_TIERS: [PositionTier; 2] = [
+    PositionTier {
+        radius: 1200,
+        delay: 0,
+    },
+    PositionTier {
+        radius: 2400,
+        delay: 30,
+    },
+];
+pub const DEFAULT_FAR_POSITION_DELAY: i32 = 100;
+pub const DEFAULT_SPECTATOR_FREQ: u16 = 8025;
+pub const DEFAULT_PUBLIC_FREQS: u16 = 2;
+pub const DEFAULT_PRIVATE_FREQ_START: u16 = 100;
+// Scores are never reset by default.
+pub const DEFAULT_SCORE_RESET_INTERVAL: i32 = 0;
+
+const TICKS_PER_MINUTE: i32 = 60 * TICKS_PER_SECOND;
+
+// Private arenas are hidden from the arena list for anyone that isn't staff.
+pub fn is_private_arena(name: &str) -> bool {
+    name.starts_with('#')
+}
+
+// The arena requested in the arena login packet.
+#[derive(Debug, PartialEq)]
+pub enum ArenaRequest {
+    AnyPublic,
+    Public(u16),
+    Named(String),
+}
+
+impl ArenaRequest {
+    // Arena type is 0xFFFF or 0xFFFE for any public arena, 0xFFFD for a named arena, otherwise it's the public arena
+    // number. The type is signed in the client, so any other negative type is also placed in any public arena instead
+    // of creating a numbered arena for it.
+    pub fn new(arena_type: u16, name: &str) -> Self {
+        match arena_type {
+            0xFFFF | 0xFFFE => ArenaRequest::AnyPublic,
+            0xFFFD => {
+                let name = name.trim().to_lowercase();
+
+                if name.is_empty() {
+                    ArenaRequest::AnyPublic
+                } else {
+                    ArenaRequest::Named(name)
+                }
+            }
+            number if number >= 0x8000 => ArenaRequest::AnyPublic,
+            number => ArenaRequest::Public(number),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PositionTier {
+    pub radius: u32,
+    pub delay: i32,
+}
+
+pub struct ArenaConfig {
+    pub map: String,
+    // Extra lvz files that are sent along with the map.
+    pub lvz_files: Vec<String>,
+
+    // Position packets are sent less often to players that are far away. The tiers are sorted by radius and the
+    // delay of the first tier containing the receiver is used. Anything outside every tier uses the far delay.
+    pub position_tiers: Vec<PositionTier>,
+    pub far_position_delay: i32,
+
+    // Ships that players can use, numbered 0 to 7.
+    pub allowed_ships: Vec<u8>,
+    pub spectator_freq: u16,
+    // Players that don't pick a freq are balanced between freqs 0 to public_freqs - 1.
+    pub public_freqs: u16,
+    // Freqs from this value up are private.
+    pub private_freq_start: u16,
+    pub allow_private_freqs: bool,
+
+    // Minutes between score resets. Zero disables them.
+    pub score_reset_interval: i32,
+
+    // Whether players can send public messages when the arena is created. Staff can toggle it with *publicchat.
+    pub public_chat: bool,
+}
+
+impl ArenaConfig {
+    pub fn load(config: &Config, arena: &str) -> Self {
+        Self {
+            map: config
+                .get_arena_str(arena, "Map")
+                .unwrap_or(DEFAULT_MAP)
+                .to_owned(),
+            lvz_files: config.get_arena_list(arena, "LevelFiles"),
+            position_tiers: load_position_tiers(config, arena),
+            far_position_delay: config.get_arena_int(
+                arena,
+                "FarPositionDelay",
+                DEFAULT_FAR_POSITION_DELAY,
+            ),
+            allowed_ships: load_allowed_ships(config, arena),
+            spectator_freq: config.get_arena_int(
+                arena,
+                "SpectatorFreq",
+                DEFAULT_SPECTATOR_FREQ as i32,
+            ) as u16,
+            public_freqs: (config.get_arena_int(arena, "PublicFreqs", DEFAULT_PUBLIC_FREQS as i32)
+                as u16)
+                .max(1),
+            private_freq_start: config.get_arena_int(
+                arena,
+                "PrivateFreqStart",
+                DEFAULT_PRIVATE_FREQ_START as i32,
+            ) as u16,
+            allow_private_freqs: config.get_arena_int(arena, "AllowPrivateFreqs", 1) != 0,
+            score_reset_interval: config
+                .get_arena_int(arena, "ScoreResetInterval", DEFAULT_SCORE_RESET_INTERVAL)
+                .max(0),
+            public_chat: config.get_arena_int(arena, "PublicChat", 1) != 0,
+        }
+    }
+
+    // Gets the minimum number of ticks between position packets for a receiver at the distance in pixels.
+    pub fn position_delay(&self, distance: u32) -> i32 {
+        self.position_tiers
+            .iter()
+            .find(|tier| distance <= tier.radius)
+            .map(|tier| tier.delay)
+            .unwrap_or(self.far_position_delay)
+    }
+}
+
+// Ships are configured by the numbers players see, 1 to 8.
+fn load_allowed_ships(config: &Config, arena: &str) -> Vec<u8> {
+    let entries = config.get_arena_list(arena, "AllowedShips");
+
+    if entries.is_empty() {
+        return (0..SHIP_COUNT as u8).collect();
+    }
+
+    entries
+        .iter()
+        .filter_map(|entry| entry.parse::<u8>().ok())
+        .filter(|ship| (1..=SHIP_COUNT as u8).contains(ship))
+        .map(|ship| ship - 1)
+        .collect()
+}
+
+// Tiers are configured as a list of radius:delay pairs.
+fn load_position_tiers(config: &Config, arena: &str) -> Vec<PositionTier> {
+    let entries = config.get_arena_list(arena, "PositionTiers");
+
+    if entries.is_empty() {
+        return DEFAULT_POSITION_TIERS.to_vec();
+    }
+
+    let mut tiers: Vec<PositionTier> = entries
+        .iter()
+        .filter_map(|entry| {
+            let (radius, delay) = entry.split_once(':')?;
+
+            Some(PositionTier {
+                radius: radius.trim().parse().ok()?,
+                delay: delay.trim().parse().ok()?,
+            })
+        })
+        .collect();
+
+    if tiers.len() != entries.len() {
+        println!("Ignoring malformed position tiers in arena {}", arena);
+    }
+
+    tiers.sort_by_key(|tier| tier.radius);
+    tiers
+}
+
+pub struct Arena {
+    pub name: String,
+    pub config: ArenaConfig,
+    pub settings: ArenaSettings,
+    pub map: Option<Map>,
+    pub lvz_files: Vec<LevelFile>,
+    pub doors: Doors,
+    // Seed the client uses for prize generation. It's sent with the door seed in the security packet.
+    pub green_seed: u32,
+    // Used for prizes that the server picks itself, such as the prize dropped on a kill.
+    prize_rng: VieRng,
+
+    pub public_chat: bool,
+}
+
+// Scores of the players that left an arena and when they were last reset. The arena manager keeps these by arena name
+// so they outlive the arena when its last player leaves.
+pub struct ScoreTable {
+    // Scores by lowercase player name. Players get their score back when they return.
+    scores: HashMap<String, Score>,
+    last_reset: Tick,
+    // Minutes between score resets. Zero never resets.
+    reset_interval: i32,
+}
+
+impl ScoreTable {
+    fn new(reset_interval: i32) -> Self {
+        Self {
+            scores: HashMap::new(),
+            last_reset: Tick::now(),
+            reset_interval,
+        }
+    }
+
+    // Clears the stored scores once the reset interval has passed. Returns true if it did.
+    fn update(&mut self, now: Tick) -> bool {
+        if self.reset_interval == 0 {
+            return false;
+        }
+
+        // Tick differences are only valid up to 2^30 ticks.
+        let interval = self
+            .reset_interval
+            .clamp(1, (1 << 30) / TICKS_PER_MINUTE - 1)
+            * TICKS_PER_MINUTE;
+
+        if now.diff(&self.last_reset) < interval {
+            return false;
+        }
+
+        self.last_reset = now;
+        self.scores.clear();
+        true
+    }
+}
+
+impl Arena {
+    pub fn new(name: &str, config: &Config) -> Self {
+        let config = ArenaConfig::load(config, name);
+        let public_chat = config.public_chat;
+        let map = Map::load(&config.map);
+
+        if map.is_none() {
+            println!(
+                "WARNING: Arena {} couldn't load map {}. Clients will play on their own copy of {}.",
+                name, config.map, STOCK_MAP_FILENAME
+            );
+        }
+
+        let lvz_files = config
+            .lvz_files
+            .iter()
+            .filter_map(|filename| LevelFile::load(filename).map(|(file, _)| file))
+            .collect();
+
+        Self {
+            name: name.to_owned(),
+            config,
+            settings: ArenaSettings::new(),
+            map,
+            lvz_files,
+            doors: Doors::new(generate_seed()),
+            green_seed: generate_seed(),
+            prize_rng: VieRng::new(generate_seed()),
+            public_chat,
+        }
+    }
+
+    pub fn update(&mut self, now: Tick) {
+        let door_delay = self.settings.door_delay();
+        let door_mode = self.settings.door_mode();
+
+        if self.doors.update(now, door_delay, door_mode)
+            && let Some(map) = &mut self.map
+        {
+            map.door_state = self.doors.state;
+        }
+    }
+
+    // Picks a prize using the weights from the arena settings. Returns 0 when every weight is zero.
+    pub fn random_prize(&mut self) -> u8 {
+        let weights = self.settings.prize_weights();
+        let total: u32 = weights.iter().map(|&weight| weight as u32).sum();
+
+        if total == 0 {
+            return 0;
+        }
+
+        let mut roll = self.prize_rng.get_next() % total;
+
+        for (index, &weight) in weights.iter().enumerate() {
+            if roll < weight as u32 {
+                return index as u8 + 1;
+            }
+
+            roll -= weight as u32;
+        }
+
+        0
+    }
+
+    // Builds the security packet containing the seeds so the client can run the same door cycle as the server.
+    pub fn security_packet(&self) -> Packet {
+        Packet::empty()
+            .concat_u8(0x18)
+            .concat_u32(self.green_seed)
+            .concat_u32(self.doors.seed)
+            .concat_u32(self.doors.seed_time.value())
+            .concat_u32(0) // Checksum key. Zero disables the client's security checks.
+    }
+
+    // Gets a downloadable file by the index used in the map request. Index 0 is the map and the lvz files follow it.
+    pub fn get_level_file(&self, index: usize) -> Option<&LevelFile> {
+        match index {
+            0 => self.map.as_ref().map(|map| &map.file),
+            index => self.lvz_files.get(index - 1),
+        }
+    }
+
+    pub fn is_ship_allowed(&self, ship: u8) -> bool {
+        ship == SHIP_SPECTATOR || self.config.allowed_ships.contains(&ship)
+    }
+
+    // Checks if a spectator can be on the freq.
+    pub fn is_freq_valid(&self, freq: u16) -> bool {
+        if freq == self.config.spectator_freq {
+            return true;
+        }
+
+        if freq >= self.settings.max_frequency() {
+            return false;
+        }
+
+        freq < self.config.private_freq_start || self.config.allow_private_freqs
+    }
+
+    // Checks if a player in a ship can join the freq when it already has population players in ships.
+    pub fn can_join_freq(&self, freq: u16, population: usize) -> bool {
+        if freq == self.config.spectator_freq || !self.is_freq_valid(freq) {
+            return false;
+        }
+
+        let limit = match freq >= self.config.private_freq_start {
+            true => self.settings.max_per_private_team(),
+            false => self.settings.max_per_team(),
+        };
+
+        limit == 0 || population < limit as usize
+    }
+
+    // Checks if the ship fits at the pixel position without overlapping any walls. Everything fits when there is no
+    // map loaded.
+    pub fn can_ship_occupy(&self, ship: u8, x: u16, y: u16) -> bool {
+        match &self.map {
+            Some(map) => map.can_occupy(x, y, self.settings.ship_radius(ship)),
+            None => true,
+        }
+    }
+
+    // Builds the map info packet. The name, checksum and size of the map and each lvz file are sent so the client can
+    // tell if its copy of each file is current.
+    pub fn map_info_packet(&self) -> Packet {
+        // Each file entry is the filename followed by its checksum and size.
+        const FILE_ENTRY_SIZE: usize = MAX_MAP_FILENAME_LEN + 8;
+
+        let mut packet = Packet::empty().concat_u8(0x29);
+
+        match &self.map {
+            Some(map) => write_file_entry(
+                &mut packet,
+                &map.file.filename,
+                map.file.checksum,
+                map.file.filesize,
+            ),
+            None => write_file_entry(
+                &mut packet,
+                STOCK_MAP_FILENAME,
+                STOCK_MAP_CHECKSUM,
+                STOCK_MAP_FILESIZE,
+            ),
+        }
+
+        for lvz in &self.lvz_files {
+            // Leave room for the reliable header.
+            if packet.size + FILE_ENTRY_SIZE > MAX_PACKET_SIZE - 6 {
+                println!(
+                    "Too many lvz files in arena {}. Skipping {}.",
+                    self.name, lvz.filename
+                );
+                break;
+            }
+
+            write_file_entry(&mut packet, &lvz.filename, lvz.checksum, lvz.filesize);
+        }
+
+        packet
+    }
+}
+
+fn write_file_entry(packet: &mut Packet, filename: &str, checksum: u32, filesize: u32) {
+    let name_len = filename.len().min(MAX_MAP_FILENAME_LEN);
+
+    packet.data[packet.size..packet.size + name_len]
+        .copy_from_slice(&filename.as_bytes()[..name_len]);
+    packet.size += MAX_MAP_FILENAME_LEN;
+    packet.write_u32(checksum);
+    packet.write_u32(filesize);
+}
+
+pub struct ArenaManager {
+    pub arenas: HashMap<String, Arena>,
+    score_tables: HashMap<String, ScoreTable>,
+
+    // Names used for public arenas 0..N. Public arenas past the end of the list use their number as the name.
+    public_names: Vec<String>,
+    // The number of players a public arena can hold before automatic assignment spills into the next one.
+    public_cap: usize,
+}
+
+impl ArenaManager {
+    pub fn new(config: &Config) -> Self {
+        let public_names = config
+            .get_list("Arena", "PublicNames")
+            .iter()
+            .map(|name| name.to_lowercase())
+            .collect();
+
+        let public_cap = config.get_int("Arena", "PublicCap", DEFAULT_PUBLIC_ARENA_CAP as i32);
+
+        // Arenas are created when players enter them, so check the default map now instead of when the first player
+        // shows up.
+        let map = config.get_str("Arena", "Map").unwrap_or(DEFAULT_MAP);
+
+        if !std::path::Path::new(MAP_DIRECTORY).join(map).is_file() {
+            println!(
+                "WARNING: Map {} isn't in the {} directory. Arenas using it will fall back to the client's {}.",
+                map, MAP_DIRECTORY, STOCK_MAP_FILENAME
+            );
+        }
+
+        Self {
+            arenas: HashMap::new(),
+            score_tables: HashMap::new(),
+            public_names,
+            public_cap: public_cap.max(1) as usize,
+        }
+    }
+
+    pub fn public_name(&self, number: u16) -> String {
+        match self.public_names.get(number as usize) {
+            Some(name) => name.clone(),
+            None => number.to_string(),
+        }
+    }
+
+    // Determines the name of the arena that the request should place the player in.
+    // The population function returns the current number of players in the named arena.
+    pub fn resolve<F>(&self, request: &ArenaRequest, population: F) -> String
+    where
+        F: Fn(&str) -> usize,
+    {
+        match request {
+            ArenaRequest::Named(name) => name.clone(),
+            ArenaRequest::Public(number) => self.public_name(*number),
+            ArenaRequest::AnyPublic => {
+                // Fill public arenas in order until one is found with room.
+                for number in 0..=u16::MAX {
+                    let name = self.public_name(number);
+
+                    if population(&name) < self.public_cap {
+                        return name;
+                    }
+                }
+
+                self.public_name(0)
+            }
+        }
+    }
+
+    pub fn get_or_create(&mut self, name: &str, config: &Config) -> &mut Arena {
+        let arena = self.arenas.entry(name.to_owned()).or_insert_with(|| {
+            println!("Creating arena {}", name);
+            Arena::new(name, config)
+        });
+
+        // An arena that is created again picks up the scores it had before it emptied out.
+        self.score_tables
+            .entry(name.to_owned())
+            .or_insert_with(|| ScoreTable::new(arena.config.score_reset_interval));
+
+        arena
+    }
+
+    // Updates every arena and score table. Returns the arenas whose scores were reset.
+    pub fn update(&mut self, now: Tick) -> Vec<String> {
+        for arena in self.arenas.values_mut() {
+            arena.update(now);
+        }
+
+        self.score_tables
+            .iter_mut()
+            .filter_map(|(name, table)| table.update(now).then(|| name.clone()))
+            .collect()
+    }
+
+    // Takes the score the player had when they last left the arena.
+    pub fn take_score(&mut self, arena: &str, name: &str) -> Score {
+        self.score_tables
+            .get_mut(arena)
+            .and_then(|table| table.scores.remove(&name.to_lowercase()))
+            .unwrap_or_default()
+    }
+
+    pub fn store_score(&mut self, arena: &str, name: &str, score: Score) {
+        if let Some(table) = self.score_tables.get_mut(arena) {
+            table.scores.insert(name.to_lowercase(), score);
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        if self.arenas.remove(name).is_some() {
+            println!("Destroying arena {}", name);
+        }
+    }
+}
+
+fn generate_seed() -> u32 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or(0);
+
+    // The generator needs a seed in the range 1..0x7FFFFFFF.
+    (nanos % 0x7FFFFFFE) + 1
+}
@@ -0,0 +1,143 @@
+use crate::arena::ARENA_SETTINGS;
+
+pub const ARENA_SETTINGS_SIZE: usize = 1428;
+pub const SHIP_COUNT: usize = 8;
+
+// Layout of the settings packet. Each ship has its own block of settings after the 4 byte header, followed by the
+// arena wide settings.
+const SHIP_SETTINGS_OFFSET: usize = 4;
+const SHIP_SETTINGS_SIZE: usize = 144;
+const SHIP_SHORT_OFFSET: usize = 8;
+
+const LONG_SET_OFFSET: usize = 1156;
+const SHORT_SET_OFFSET: usize = 1252;
+const BYTE_SET_OFFSET: usize = 1368;
+const PRIZE_WEIGHT_OFFSET: usize = 1400;
+
+// Prizes are numbered 1 to 28 and each has a weight at the end of the settings.
+pub const PRIZE_COUNT: usize = 28;
+
+// Index of the bitfield in the ship short settings that contains the ship radius.
+const SHIP_MISC_BITFIELD: usize = 10;
+const DEFAULT_SHIP_RADIUS: u16 = 14;
+
+const SHIP_SHORT_MAXIMUM_SPEED: usize = 18;
+const SHIP_SHORT_MAXIMUM_ENERGY: usize = 20;
+
+const LONG_MAX_FREQUENCY: usize = 7;
+const LONG_REPEL_SPEED: usize = 8;
+const LONG_ROCKET_SPEED: usize = 15;
+
+const SHORT_BOUNTY_INCREASE_FOR_KILL: usize = 9;
+const SHORT_FLAGGER_KILL_MULTIPLIER: usize = 19;
+const SHORT_DOOR_DELAY: usize = 27;
+const SHORT_DOOR_MODE: usize = 29;
+
+const BYTE_MAX_PER_TEAM: usize = 5;
+const BYTE_MAX_PER_PRIVATE_TEAM: usize = 6;
+
+pub struct ArenaSettings {
+    pub data: [u8; ARENA_SETTINGS_SIZE],
+}
+
+impl ArenaSettings {
+    pub fn new() -> Self {
+        Self {
+            data: ARENA_SETTINGS,
+        }
+    }
+
+    // Radius of the ship in pixels.
+    pub fn ship_radius(&self, ship: u8) -> u16 {
+        let bitfield = self.ship_short(ship, SHIP_MISC_BITFIELD) as u16;
+        let radius = (bitfield >> 3) & 0xFF;
+
+        if radius == 0 {
+            return DEFAULT_SHIP_RADIUS;
+        }
+
+        radius
+    }
+
+    pub fn ship_maximum_energy(&self, ship: u8) -> i16 {
+        self.ship_short(ship, SHIP_SHORT_MAXIMUM_ENERGY)
+    }
+
+    // The fastest a ship can legitimately move. Rockets and repels can push a ship past its maximum speed.
+    pub fn ship_fastest_speed(&self, ship: u8) -> u32 {
+        let maximum_speed = self.ship_short(ship, SHIP_SHORT_MAXIMUM_SPEED).max(0) as u32;
+        let rocket_speed = self.long(LONG_ROCKET_SPEED).max(0) as u32;
+        let repel_speed = self.long(LONG_REPEL_SPEED).max(0) as u32;
+
+        maximum_speed.max(rocket_speed).max(repel_speed)
+    }
+
+    // Frequencies must be below this value.
+    pub fn max_frequency(&self) -> u16 {
+        self.long(LONG_MAX_FREQUENCY).clamp(0, u16::MAX as i32) as u16
+    }
+
+    // Maximum number of players in ships on a public frequency. Zero means there's no limit.
+    pub fn max_per_team(&self) -> u8 {
+        self.byte(BYTE_MAX_PER_TEAM)
+    }
+
+    // Maximum number of players in ships on a private frequency. Zero means there's no limit.
+    pub fn max_per_private_team(&self) -> u8 {
+        self.byte(BYTE_MAX_PER_PRIVATE_TEAM)
+    }
+
+    // Bounty that the killer gains for each kill.
+    pub fn bounty_increase_for_kill(&self) -> u16 {
+        self.short(SHORT_BOUNTY_INCREASE_FOR_KILL).max(0) as u16
+    }
+
+    // Extra multiples of the kill reward given when the killer is carrying flags. 1 gives double points.
+    pub fn flagger_kill_multiplier(&self) -> u32 {
+        self.short(SHORT_FLAGGER_KILL_MULTIPLIER).max(0) as u32
+    }
+
+    // Relative chance of each prize being picked. Index 0 is prize 1.
+    pub fn prize_weights(&self) -> &[u8] {
+        &self.data[PRIZE_WEIGHT_OFFSET..PRIZE_WEIGHT_OFFSET + PRIZE_COUNT]
+    }
+
+    // How often the doors change in ticks.
+    pub fn door_delay(&self) -> i16 {
+        self.short(SHORT_DOOR_DELAY)
+    }
+
+    pub fn door_mode(&self) -> i16 {
+        self.short(SHORT_DOOR_MODE)
+    }
+
+    fn long(&self, index: usize) -> i32 {
+        let offset = LONG_SET_OFFSET + index * 4;
+
+        i32::from_le_bytes(self.data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn short(&self, index: usize) -> i16 {
+        let offset = SHORT_SET_OFFSET + index * 2;
+
+        i16::from_le_bytes(self.data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn byte(&self, index: usize) -> u8 {
+        self.data[BYTE_SET_OFFSET + index]
+    }
+
+    fn ship_short(&self, ship: u8, index: usize) -> i16 {
+        let ship = (ship as usize).min(SHIP_COUNT - 1);
+        let offset =
+            SHIP_SETTINGS_OFFSET + ship * SHIP_SETTINGS_SIZE + SHIP_SHORT_OFFSET + index * 2;
+
+        i16::from_le_bytes(self.data[offset..offset + 2].try_into().unwrap())
+    }
+}
+
+impl Default for ArenaSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
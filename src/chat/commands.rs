@@ -0,0 +1,146 @@
+use crate::chat::{ChatType, MAX_MUTE_SECONDS, build_chat_packet, mute_end, parse_channel_list};
+use crate::clock::Tick;
+use crate::command::{Command, CommandContext, CommandRegistry, CommandTarget, TargetKind};
+use crate::player::INVALID_PLAYER_ID;
+use crate::{Connection, Game};
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+
+// Commands for chat channels and muting.
+pub fn register(commands: &mut CommandRegistry) {
+    commands.register(Command {
+        name: "chat",
+        usage: "[channel,channel,...]",
+        help: "Sets your chat channels or lists them. ?chat= leaves every channel. Send ;N;message to talk on channel N.",
+        capability: None,
+        staff: false,
+        targets: &[TargetKind::Arena],
+        handler: command_chat,
+    });
+
+    commands.register(Command {
+        name: "shutup",
+        usage: "[seconds]",
+        help: "Mutes the player or unmutes them if they are already muted.",
+        capability: Some("cmd_shutup"),
+        staff: false,
+        targets: &[TargetKind::Player],
+        handler: command_shutup,
+    });
+
+    commands.register(Command {
+        name: "publicchat",
+        usage: "",
+        help: "Turns public chat in the arena on or off.",
+        capability: Some("cmd_publicchat"),
+        staff: false,
+        targets: &[TargetKind::Arena],
+        handler: command_publicchat,
+    });
+}
+
+// Sets the player's chat channels from ?chat=a,b,c or lists them when there's no list.
+fn command_chat(
+    game: &mut Game,
+    game_socket: &UdpSocket,
+    connections: &mut HashMap<SocketAddr, Connection>,
+    context: &CommandContext,
+) {
+    let pid = context.sender;
+
+    let Some(player) = game.player_manager.players.get_mut(&pid) else {
+        return;
+    };
+
+    // ?chat shows the channels and ?chat= with nothing after it leaves all of them.
+    if let Some(args) = context.args {
+        player.chat_channels = parse_channel_list(args);
+        game.channels
+            .set(&player.name, player.chat_channels.clone());
+    }
+
+    let message = match player.chat_channels.is_empty() {
+        true => "You are not on any chat channels.".to_owned(),
+        false => format!("Chat channels: {}", player.chat_channels.join(",")),
+    };
+
+    game.send_arena_message(game_socket, connections, pid, &message);
+}
+
+// *shutup [seconds] mutes the targeted player or unmutes them if they are already muted.
+fn command_shutup(
+    game: &mut Game,
+    game_socket: &UdpSocket,
+    connections: &mut HashMap<SocketAddr, Connection>,
+    context: &CommandContext,
+) {
+    let CommandTarget::Player(target) = context.target else {
+        return;
+    };
+
+    let now = Tick::now();
+    let seconds = context
+        .parse_arg::<i32>(0)
+        .unwrap_or(game.chat.shutup_time)
+        .clamp(0, MAX_MUTE_SECONDS);
+
+    let Some(player) = game.player_manager.players.get_mut(&target) else {
+        return;
+    };
+
+    let muted = player.muted_until.is_some_and(|until| until.gt(&now));
+
+    let (player_message, staff_message) = match muted {
+        true => {
+            player.muted_until = None;
+
+            (
+                "You have been unmuted.".to_owned(),
+                format!("{} has been unmuted.", player.name),
+            )
+        }
+        false => {
+            player.chat_times.clear();
+            player.muted_until = Some(mute_end(now, seconds));
+
+            (
+                format!("You have been muted for {} seconds.", seconds),
+                format!("{} has been muted for {} seconds.", player.name, seconds),
+            )
+        }
+    };
+
+    game.send_arena_message(game_socket, connections, target, &player_message);
+    game.send_arena_message(game_socket, connections, context.sender, &staff_message);
+}
+
+// *publicchat toggles public chat in the staff member's arena.
+fn command_publicchat(
+    game: &mut Game,
+    game_socket: &UdpSocket,
+    connections: &mut HashMap<SocketAddr, Connection>,
+    context: &CommandContext,
+) {
+    let Some(arena_name) = game
+        .player_manager
+        .players
+        .get(&context.sender)
+        .and_then(|player| player.arena.clone())
+    else {
+        return;
+    };
+
+    let Some(arena) = game.arena_manager.arenas.get_mut(&arena_name) else {
+        return;
+    };
+
+    arena.public_chat = !arena.public_chat;
+
+    let message = match arena.public_chat {
+        true => "Public chat has been enabled.",
+        false => "Public chat has been disabled.",
+    };
+
+    let packet = build_chat_packet(ChatType::Arena, 0, INVALID_PLAYER_ID, message);
+    game.send_reliable_to_arena(game_socket, connections, &arena_name, &packet);
+}
@@ -0,0 +1,283 @@
+use crate::clock::{TICKS_PER_SECOND, Tick};
+use crate::config::Config;
+use crate::packet::{MAX_PACKET_SIZE, Packet, read_fixed_string};
+use crate::player::PlayerId;
+use std::collections::HashMap;
+
+pub mod commands;
+
+// The client won't send or display messages longer than this.
+pub const MAX_CHAT_LEN: usize = 250;
+
+// Public messages that start with this are sent to the moderators instead of the arena.
+pub const MOD_CHAT_PREFIX: char = '\\';
+
+// The client can only address this many chat channels.
+pub const MAX_CHAT_CHANNELS: usize = 10;
+pub const MAX_CHANNEL_NAME_LEN: usize = 32;
+// File that stores each player's chat channels so they are restored the next time they log in.
+pub const CHANNEL_PATH: &str = "channels.txt";
+
+pub const DEFAULT_FLOOD_LIMIT: i32 = 10;
+pub const DEFAULT_FLOOD_WINDOW: i32 = 500;
+pub const DEFAULT_FLOOD_MUTE_TIME: i32 = 60;
+pub const DEFAULT_SHUTUP_TIME: i32 = 600;
+
+pub struct ChatConfig {
+    // Number of messages a player can send within the flood window before they are muted.
+    pub flood_limit: usize,
+    // Ticks that each message counts towards the flood limit.
+    pub flood_window: i32,
+    // Seconds that a player is muted for flooding.
+    pub flood_mute_time: i32,
+    // Seconds that *shutup mutes a player for when no duration is given.
+    pub shutup_time: i32,
+}
+
+// Mutes end at a tick, and ticks can only be compared while they are less than 2^30 ticks apart.
+pub const MAX_MUTE_SECONDS: i32 = (1 << 30) / TICKS_PER_SECOND - 1;
+
+// The tick that a mute starting now ends at. The length is limited to MAX_MUTE_SECONDS.
+pub fn mute_end(now: Tick, seconds: i32) -> Tick {
+    let ticks = seconds.clamp(0, MAX_MUTE_SECONDS) * TICKS_PER_SECOND;
+
+    Tick::new(now.value().wrapping_add(ticks as u32))
+}
+
+impl ChatConfig {
+    pub fn load(config: &Config) -> Self {
+        Self {
+            flood_limit: config
+                .get_int("Chat", "FloodLimit", DEFAULT_FLOOD_LIMIT)
+                .max(1) as usize,
+            flood_window: config
+                .get_int("Chat", "FloodWindow", DEFAULT_FLOOD_WINDOW)
+                .max(0),
+            flood_mute_time: config
+                .get_int("Chat", "FloodMuteTime", DEFAULT_FLOOD_MUTE_TIME)
+                .clamp(0, MAX_MUTE_SECONDS),
+            shutup_time: config
+                .get_int("Chat", "ShutupTime", DEFAULT_SHUTUP_TIME)
+                .clamp(0, MAX_MUTE_SECONDS),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChatType {
+    // Green server message to the arena.
+    Arena,
+    PublicMacro,
+    Public,
+    Team,
+    // Message to every player on the freq of the target player.
+    Frequency,
+    Private,
+    // Red message that only the server sends.
+    Warning,
+    // Private message to a player by name in any arena. The client sends it as ":name:message".
+    RemotePrivate,
+    SysopWarning,
+    Channel,
+}
+
+impl ChatType {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ChatType::Arena),
+            1 => Some(ChatType::PublicMacro),
+            2 => Some(ChatType::Public),
+            3 => Some(ChatType::Team),
+            4 => Some(ChatType::Frequency),
+            5 => Some(ChatType::Private),
+            6 => Some(ChatType::Warning),
+            7 => Some(ChatType::RemotePrivate),
+            8 => Some(ChatType::SysopWarning),
+            9 => Some(ChatType::Channel),
+            _ => None,
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            ChatType::Arena => 0,
+            ChatType::PublicMacro => 1,
+            ChatType::Public => 2,
+            ChatType::Team => 3,
+            ChatType::Frequency => 4,
+            ChatType::Private => 5,
+            ChatType::Warning => 6,
+            ChatType::RemotePrivate => 7,
+            ChatType::SysopWarning => 8,
+            ChatType::Channel => 9,
+        }
+    }
+}
+
+// C2S chat packet
+#[derive(Clone, Debug)]
+pub struct ChatMessage {
+    pub kind: ChatType,
+    pub sound: u8,
+    // The target player for private and frequency messages.
+    pub target: PlayerId,
+    pub text: String,
+}
+
+impl ChatMessage {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 6 {
+            return None;
+        }
+
+        let kind = ChatType::from_u8(buf[1])?;
+        let mut text = read_fixed_string(&buf[5..]);
+
+        truncate_chat(&mut text);
+
+        Some(Self {
+            kind,
+            sound: buf[2],
+            target: u16::from_le_bytes(buf[3..5].try_into().unwrap()),
+            text,
+        })
+    }
+}
+
+// Splits the ":name:message" format of a remote private message.
+pub fn parse_remote_private(text: &str) -> Option<(&str, &str)> {
+    let (name, message) = text.strip_prefix(':')?.split_once(':')?;
+
+    if name.is_empty() {
+        return None;
+    }
+
+    Some((name, message))
+}
+
+// Splits a channel message into the 0 based channel index and the message. The client sends "N;message" for
+// channel N and a message without a channel number goes to the first channel.
+pub fn parse_channel_message(text: &str) -> (usize, &str) {
+    if let Some((number, message)) = text.split_once(';')
+        && let Ok(number) = number.parse::<usize>()
+        && (1..=MAX_CHAT_CHANNELS).contains(&number)
+    {
+        return (number - 1, message);
+    }
+
+    (0, text)
+}
+
+// Parses the comma separated list from ?chat=. Names are case insensitive and duplicates are removed.
+pub fn parse_channel_list(list: &str) -> Vec<String> {
+    let mut channels: Vec<String> = vec![];
+
+    for name in list.split(',') {
+        let name = name.trim().to_lowercase();
+
+        if name.is_empty() || name.len() > MAX_CHANNEL_NAME_LEN || channels.contains(&name) {
+            continue;
+        }
+
+        if channels.len() == MAX_CHAT_CHANNELS {
+            break;
+        }
+
+        channels.push(name);
+    }
+
+    channels
+}
+
+// Chat channels of every player that has set them, stored by lowercase name. Each line of the file is the player name
+// followed by a colon and their channel list.
+pub struct ChannelStore {
+    path: String,
+    channels: HashMap<String, Vec<String>>,
+}
+
+impl ChannelStore {
+    pub fn load(path: &str) -> Self {
+        let mut channels = HashMap::new();
+
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                for line in contents.lines() {
+                    if let Some((name, list)) = line.split_once(':') {
+                        channels.insert(name.trim().to_lowercase(), parse_channel_list(list));
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => println!("Failed to read chat channels from {}: {}", path, e),
+        }
+
+        Self {
+            path: path.to_owned(),
+            channels,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Vec<String> {
+        self.channels
+            .get(&name.to_lowercase())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // Stores the player's channels and writes every player's channels back to the file.
+    pub fn set(&mut self, name: &str, channels: Vec<String>) {
+        let name = name.to_lowercase();
+
+        match channels.is_empty() {
+            true => self.channels.remove(&name),
+            false => self.channels.insert(name, channels),
+        };
+
+        let mut names: Vec<&String> = self.channels.keys().collect();
+        names.sort();
+
+        let contents: String = names
+            .iter()
+            .map(|name| format!("{}:{}\n", name, self.channels[*name].join(",")))
+            .collect();
+
+        if let Err(e) = std::fs::write(&self.path, contents) {
+            println!("Failed to write chat channels to {}: {}", self.path, e);
+        }
+    }
+}
+
+// Builds the S2C chat packet 0x07. The text is null terminated.
+pub fn build_chat_packet(kind: ChatType, sound: u8, pid: PlayerId, text: &str) -> Packet {
+    // Leave room for the reliable header and the null terminator.
+    const MAX_TEXT_SIZE: usize = MAX_PACKET_SIZE - 6 - 5 - 1;
+
+    let mut packet = Packet::empty()
+        .concat_u8(0x07)
+        .concat_u8(kind.to_u8())
+        .concat_u8(sound)
+        .concat_u16(pid);
+
+    let text = &text.as_bytes()[..text.len().min(MAX_TEXT_SIZE)];
+
+    packet.data[packet.size..packet.size + text.len()].copy_from_slice(text);
+    packet.size += text.len();
+    packet.write_u8(0);
+
+    packet
+}
+
+fn truncate_chat(text: &mut String) {
+    if text.len() <= MAX_CHAT_LEN {
+        return;
+    }
+
+    let mut end = MAX_CHAT_LEN;
+
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    text.truncate(end);
+}
@@ -1,5 +1,8 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
+pub const TICKS_PER_SECOND: i32 = 100;
+
+#[derive(Copy, Clone, Debug)]
 pub struct Tick {
     value: u32,
 }
@@ -28,7 +31,8 @@ impl Tick {
         let first: i32 = (self.value << 1) as i32;
         let second: i32 = (other.value << 1) as i32;
 
-        (first - second) >> 1
+        // Ticks wrap around, so the difference does too. Client timestamps can be anywhere in the tick range.
+        first.wrapping_sub(second) >> 1
     }
 
     pub fn gt(&self, other: &Tick) -> bool {
@@ -43,3 +47,23 @@ impl Tick {
         self.value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_wraps_between_opposite_sign_ticks() {
+        let positive = Tick::new(0x40000000);
+        let negative = Tick::new(0x3FFFFFFF);
+
+        assert_eq!(positive.diff(&negative), 1);
+        assert_eq!(negative.diff(&positive), -1);
+
+        let early = Tick::new(0);
+
+        assert_eq!(positive.diff(&early), -0x40000000);
+        assert_eq!(Tick::new(0x7FFFFFFF).diff(&Tick::new(1)), -2);
+        assert!(Tick::new(5).gt(&Tick::new(0x7FFFFFF0)));
+    }
+}
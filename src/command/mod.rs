@@ -0,0 +1,195 @@
+use crate::player::PlayerId;
+use crate::{Connection, Game};
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+
+// Chat text starting with either of these is a command instead of a message.
+pub const COMMAND_PREFIXES: [char; 2] = ['?', '*'];
+
+pub fn is_command(text: &str) -> bool {
+    text.starts_with(COMMAND_PREFIXES)
+}
+
+// Who a command was sent to. Public messages target the arena, private messages target the player and team or
+// frequency messages target the freq.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CommandTarget {
+    Arena,
+    Player(PlayerId),
+    Freq(u16),
+}
+
+// The kinds of targets a command accepts.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TargetKind {
+    Arena,
+    Player,
+    Freq,
+}
+
+impl CommandTarget {
+    pub fn kind(&self) -> TargetKind {
+        match self {
+            CommandTarget::Arena => TargetKind::Arena,
+            CommandTarget::Player(_) => TargetKind::Player,
+            CommandTarget::Freq(_) => TargetKind::Freq,
+        }
+    }
+}
+
+pub struct CommandContext<'a> {
+    pub sender: PlayerId,
+    pub target: CommandTarget,
+    // Everything after the command name and its separator. None when there's no separator, so "?chat=" with nothing
+    // after it can be told apart from "?chat".
+    pub args: Option<&'a str>,
+}
+
+impl CommandContext<'_> {
+    // Gets an argument by its index in the whitespace separated arguments.
+    pub fn arg(&self, index: usize) -> Option<&str> {
+        self.args?.split_whitespace().nth(index)
+    }
+
+    pub fn parse_arg<T: std::str::FromStr>(&self, index: usize) -> Option<T> {
+        self.arg(index)?.parse().ok()
+    }
+}
+
+pub(crate) type CommandHandler =
+    fn(&mut Game, &UdpSocket, &mut HashMap<SocketAddr, Connection>, &CommandContext);
+
+#[derive(Copy, Clone)]
+pub struct Command {
+    pub name: &'static str,
+    // Arguments shown by ?help, such as "<prize> [count]".
+    pub usage: &'static str,
+    pub help: &'static str,
+    // Capability that a player needs to use the command. None lets everyone use it.
+    pub capability: Option<&'static str>,
+    // Shows the command with * even though everyone can use it, such as *login which is only useful to staff.
+    pub staff: bool,
+    pub targets: &'static [TargetKind],
+    pub(crate) handler: CommandHandler,
+}
+
+impl Command {
+    pub fn accepts(&self, target: CommandTarget) -> bool {
+        self.targets.contains(&target.kind())
+    }
+
+    // Staff commands are written with * and everything else with ?. Either prefix runs the command.
+    pub fn prefix(&self) -> char {
+        match self.staff || self.capability.is_some() {
+            true => '*',
+            false => '?',
+        }
+    }
+}
+
+pub struct CommandRegistry {
+    commands: Vec<Command>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self { commands: vec![] }
+    }
+
+    pub fn register(&mut self, command: Command) {
+        if self.find(command.name).is_some() {
+            println!("Command {} is already registered.", command.name);
+            return;
+        }
+
+        self.commands.push(command);
+    }
+
+    // Command names are case insensitive.
+    pub fn find(&self, name: &str) -> Option<Command> {
+        self.commands
+            .iter()
+            .find(|command| command.name.eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Command> {
+        self.commands.iter()
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Splits "?name args" or "?name=args" into the name and the arguments.
+pub fn parse_command(text: &str) -> Option<(&str, Option<&str>)> {
+    let text = text.trim().strip_prefix(COMMAND_PREFIXES)?;
+
+    let (name, args) = match text.split_once(['=', ' ']) {
+        Some((name, args)) => (name, Some(args.trim())),
+        None => (text, None),
+    };
+
+    if name.is_empty() {
+        return None;
+    }
+
+    Some((name, args))
+}
+
+// Commands that are part of the command system itself.
+pub fn register(commands: &mut CommandRegistry) {
+    commands.register(Command {
+        name: "help",
+        usage: "[command]",
+        help: "Lists the commands you can use or shows how to use one.",
+        capability: None,
+        staff: false,
+        targets: &[TargetKind::Arena],
+        handler: command_help,
+    });
+}
+
+// ?help lists the commands that the player can use. ?help <command> shows how to use that command.
+fn command_help(
+    game: &mut Game,
+    game_socket: &UdpSocket,
+    connections: &mut HashMap<SocketAddr, Connection>,
+    context: &CommandContext,
+) {
+    let Some(player) = game.player_manager.players.get(&context.sender) else {
+        return;
+    };
+
+    let lines = match context.arg(0) {
+        Some(name) => match game
+            .commands
+            .find(name.trim_start_matches(COMMAND_PREFIXES))
+        {
+            Some(command) if game.can_use_command(player, &command) => vec![
+                format!("{}{} {}", command.prefix(), command.name, command.usage)
+                    .trim_end()
+                    .to_owned(),
+                command.help.to_owned(),
+            ],
+            _ => vec![format!("Unknown command: {}", name)],
+        },
+        None => {
+            let names: Vec<&str> = game
+                .commands
+                .iter()
+                .filter(|command| game.can_use_command(player, command))
+                .map(|command| command.name)
+                .collect();
+
+            vec![format!("Commands: {}", names.join(", "))]
+        }
+    };
+
+    for line in lines {
+        game.send_arena_message(game_socket, connections, context.sender, &line);
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+pub const CONFIG_PATH: &str = "skew.conf";
+
+// Simple ini style config. Section and key names are case-insensitive.
+//
+// [Section]
+// Key = Value
+// ; Comment
+pub struct Config {
+    sections: HashMap<String, HashMap<String, String>>,
+}
+
+impl Config {
+    pub fn empty() -> Self {
+        Self {
+            sections: HashMap::new(),
+        }
+    }
+
+    // Loads the config file at path. A missing file results in an empty config so every lookup falls back to its
+    // default value.
+    pub fn load(path: &str) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(e) => {
+                println!("Failed to read config {}: {}", path, e);
+                Self::empty()
+            }
+        }
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let mut config = Self::empty();
+        let mut section = String::new();
+
+        for line in contents.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_lowercase();
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                println!("Ignoring malformed config line: {}", line);
+                continue;
+            };
+
+            config
+                .sections
+                .entry(section.clone())
+                .or_default()
+                .insert(key.trim().to_lowercase(), value.trim().to_owned());
+        }
+
+        config
+    }
+
+    pub fn get_str(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections
+            .get(&section.to_lowercase())
+            .and_then(|keys| keys.get(&key.to_lowercase()))
+            .map(|value| value.as_str())
+    }
+
+    // Looks up an arena key in the arena's own section, [Arena:name], before falling back to the shared [Arena] section.
+    pub fn get_arena_str(&self, arena: &str, key: &str) -> Option<&str> {
+        self.get_str(&format!("Arena:{}", arena), key)
+            .or_else(|| self.get_str("Arena", key))
+    }
+
+    pub fn get_int(&self, section: &str, key: &str, default: i32) -> i32 {
+        self.get_str(section, key)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+
+    // Returns the comma separated values of a key with empty entries removed.
+    pub fn get_list(&self, section: &str, key: &str) -> Vec<String> {
+        split_list(self.get_str(section, key))
+    }
+
+    pub fn get_arena_int(&self, arena: &str, key: &str, default: i32) -> i32 {
+        self.get_arena_str(arena, key)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+
+    pub fn get_arena_list(&self, arena: &str, key: &str) -> Vec<String> {
+        split_list(self.get_arena_str(arena, key))
+    }
+}
+
+fn split_list(value: Option<&str>) -> Vec<String> {
+    match value {
+        Some(value) => value
+            .split(',')
+            .map(|entry| entry.trim())
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| entry.to_owned())
+            .collect(),
+        None => Vec::new(),
+    }
+}
@@ -0,0 +1,156 @@
+use crate::config::Config;
+use crate::packet::read_fixed_string;
+
+pub const DEFAULT_MAX_PLAYERS: i32 = 250;
+
+// Size of the VIE login packet 0x09. The Continuum login packet 0x24 is the same followed by extra data.
+pub const VIE_LOGIN_PACKET_SIZE: usize = 101;
+pub const CONTINUUM_LOGIN_PACKET_SIZE: usize = 165;
+
+// Which client sent the login.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ClientKind {
+    Vie,
+    Continuum,
+}
+
+// The C2S login packet 0x09 or 0x24.
+#[derive(Clone, Debug)]
+pub struct LoginRequest {
+    pub client: ClientKind,
+    // Set when the player confirmed registering a name that doesn't have an account.
+    pub new_user: bool,
+    pub name: String,
+    pub password: String,
+    pub machine_id: u32,
+    pub connect_type: u8,
+    // Minutes from UTC as reported by the client.
+    pub timezone_bias: i16,
+    pub client_version: u16,
+    pub permission_id: u32,
+    // Data that only Continuum sends after the VIE fields, which includes its own machine identifiers.
+    pub continuum_data: Vec<u8>,
+}
+
+impl LoginRequest {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let client = match buf.first()? {
+            0x09 if buf.len() >= VIE_LOGIN_PACKET_SIZE => ClientKind::Vie,
+            0x24 if buf.len() >= CONTINUUM_LOGIN_PACKET_SIZE => ClientKind::Continuum,
+            _ => return None,
+        };
+
+        let continuum_data = match client {
+            ClientKind::Vie => vec![],
+            ClientKind::Continuum => {
+                buf[VIE_LOGIN_PACKET_SIZE..CONTINUUM_LOGIN_PACKET_SIZE].to_vec()
+            }
+        };
+
+        // Bytes 73 to 75 and 77 to 85 are unused by the server and the packet ends with 12 reserved bytes.
+        Some(Self {
+            client,
+            new_user: buf[1] != 0,
+            name: read_fixed_string(&buf[2..34]),
+            password: read_fixed_string(&buf[34..66]),
+            machine_id: u32::from_le_bytes(buf[66..70].try_into().unwrap()),
+            connect_type: buf[70],
+            timezone_bias: i16::from_le_bytes(buf[71..73].try_into().unwrap()),
+            client_version: u16::from_le_bytes(buf[75..77].try_into().unwrap()),
+            permission_id: u32::from_le_bytes(buf[85..89].try_into().unwrap()),
+            continuum_data,
+        })
+    }
+}
+
+// What to do when a name logs in while it's already logged in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DuplicatePolicy {
+    // Disconnect the existing session and let the new one in.
+    Kick,
+    // Keep the existing session and refuse the new one.
+    Refuse,
+}
+
+impl DuplicatePolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "kick" => Some(DuplicatePolicy::Kick),
+            "refuse" => Some(DuplicatePolicy::Refuse),
+            _ => None,
+        }
+    }
+}
+
+pub struct LoginConfig {
+    // Players past this many are refused with the server full response.
+    pub max_players: usize,
+    // Names without an account can register one by confirming the new name prompt in the client.
+    pub allow_new_accounts: bool,
+    // Set to false to refuse every login, such as before shutting the server down.
+    pub accept_new_connections: bool,
+    // Only players in a staff group can log in.
+    pub staff_only: bool,
+    pub duplicate_policy: DuplicatePolicy,
+}
+
+impl LoginConfig {
+    pub fn load(config: &Config) -> Self {
+        let duplicate_policy = match config.get_str("Login", "DuplicateLogin") {
+            Some(value) => DuplicatePolicy::parse(value).unwrap_or_else(|| {
+                println!("Unknown duplicate login policy {}. Using kick.", value);
+                DuplicatePolicy::Kick
+            }),
+            None => DuplicatePolicy::Kick,
+        };
+
+        Self {
+            max_players: config
+                .get_int("Login", "MaxPlayers", DEFAULT_MAX_PLAYERS)
+                .max(1) as usize,
+            allow_new_accounts: config.get_int("Login", "AllowNewAccounts", 1) != 0,
+            accept_new_connections: config.get_int("Login", "AcceptNewConnections", 1) != 0,
+            staff_only: config.get_int("Login", "StaffOnly", 0) != 0,
+            duplicate_policy,
+        }
+    }
+}
+
+// Response codes sent in the S2C login response 0x0A. Only the codes the server can return are listed. The client also
+// understands spectator only (0x06), too many points (0x07), too slow (0x08), no scores (0x0D), too low usage (0x0F),
+// demographics (0x10) and the demo version codes (0x11 and 0x12), but the server has no score limits or usage
+// tracking, and neither login packet says whether the client is a demo.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LoginResponse {
+    Ok,
+    // The name doesn't have an account. The client offers to register it and logs in again as a new user.
+    NewName,
+    BadPassword,
+    ServerFull,
+    LockedOut,
+    NoPermission,
+    // The zone is restricted to staff.
+    NoPermission2,
+    NoNewConnections,
+    BadName,
+    OffensiveName,
+    ServerBusy,
+}
+
+impl LoginResponse {
+    pub fn code(self) -> u8 {
+        match self {
+            LoginResponse::Ok => 0x00,
+            LoginResponse::NewName => 0x01,
+            LoginResponse::BadPassword => 0x02,
+            LoginResponse::ServerFull => 0x03,
+            LoginResponse::LockedOut => 0x04,
+            LoginResponse::NoPermission => 0x05,
+            LoginResponse::NoPermission2 => 0x09,
+            LoginResponse::NoNewConnections => 0x0A,
+            LoginResponse::BadName => 0x0B,
+            LoginResponse::OffensiveName => 0x0C,
+            LoginResponse::ServerBusy => 0x0E,
+        }
+    }
+}
@@ -1,15 +1,36 @@
-use crate::arena::ARENA_SETTINGS;
-use crate::clock::Tick;
+use crate::account::{ACCOUNT_PATH, AccountStore};
+use crate::arena::{ArenaManager, ArenaRequest, is_private_arena};
+use crate::chat::{
+    CHANNEL_PATH, ChannelStore, ChatConfig, ChatMessage, ChatType, MOD_CHAT_PREFIX,
+    build_chat_packet, mute_end, parse_channel_message, parse_remote_private,
+};
+use crate::clock::{TICKS_PER_SECOND, Tick};
+use crate::command::{
+    Command, CommandContext, CommandRegistry, CommandTarget, is_command, parse_command,
+};
+use crate::config::{CONFIG_PATH, Config};
+use crate::login::{ClientKind, DuplicatePolicy, LoginConfig, LoginRequest, LoginResponse};
+use crate::packet::position::PositionPacket;
 use crate::packet::sequencer::*;
-use crate::packet::{MAX_PACKET_SIZE, Packet};
+use crate::packet::{MAX_PACKET_SIZE, Packet, read_fixed_string};
 use crate::player::*;
+use crate::security::filter::{FilterConfig, NameViolation};
+use crate::security::group::{DEFAULT_GROUP, Groups};
+use crate::security::{PositionViolation, SecurityConfig, ViolationPolicy};
 use std::collections::HashMap;
 use std::net::{SocketAddr, UdpSocket};
 
+pub mod account;
 pub mod arena;
+pub mod chat;
 pub mod clock;
+pub mod command;
+pub mod config;
+pub mod login;
+pub mod map;
 pub mod packet;
 pub mod player;
+pub mod security;
 
 struct Connection {
     addr: SocketAddr,
@@ -17,6 +38,8 @@ struct Connection {
 
     player_id: PlayerId,
     last_packet_time: Tick,
+    // Difference between the server time and the client time from the last sync request.
+    time_offset: Option<i32>,
 
     connected: bool,
 }
@@ -28,14 +51,21 @@ impl Connection {
             packet_sequencer: PacketSequencer::new(),
             player_id: INVALID_PLAYER_ID,
             last_packet_time: Tick::now(),
+            time_offset: None,
             connected: true,
         }
     }
 
+    // Converts a client timestamp to server time using the offset from the client's sync request.
+    fn to_server_time(&self, timestamp: u32) -> Option<Tick> {
+        self.time_offset
+            .map(|offset| Tick::new(timestamp.wrapping_add(offset as u32)))
+    }
+
     fn send(&mut self, game_socket: &UdpSocket, packet: Packet) -> std::io::Result<()> {
         let buf = &packet.data[..packet.size];
         println!("Sending: {:?}", buf);
-        game_socket.send_to(buf, &self.addr)?;
+        game_socket.send_to(buf, self.addr)?;
         Ok(())
     }
 
@@ -56,14 +86,14 @@ impl Connection {
         self.packet_sequencer.reliable_sent.push(rel_mesg);
         self.packet_sequencer.increment_id();
 
-        return true;
+        true
     }
 
     fn send_small_chunked_message(&mut self, game_socket: &UdpSocket, message: &[u8]) {
         // Header size includes reliable message header and small chunk header size
         const HEADER_SIZE: usize = 2 + 6;
 
-        let mut current = &message[..];
+        let mut current = message;
 
         while !current.is_empty() {
             let mut size = current.len();
@@ -87,6 +117,42 @@ impl Connection {
         }
     }
 
+    // Queues a message that is too large for small chunks. It's sent over time by send_outbound_chunks as acks
+    // come in.
+    fn send_huge_chunked_message(&mut self, message: &[u8]) {
+        const MAX_OUTBOUND_CHUNKS: usize = 20;
+
+        self.packet_sequencer
+            .outbound_chunked
+            .push_back(OutboundChunkedPacket::new(message, MAX_OUTBOUND_CHUNKS));
+    }
+
+    fn send_outbound_chunks(&mut self, game_socket: &UdpSocket) {
+        while let Some(chunk) = self.packet_sequencer.next() {
+            if !self.send_reliable_message(game_socket, &chunk.data[..chunk.size]) {
+                println!("Failed to send huge chunk to {:?}", self.addr);
+                break;
+            }
+        }
+    }
+
+    // Sends the 0x0A login response. Any response other than Ok makes the client show an error and disconnect.
+    fn send_login_response(&mut self, game_socket: &UdpSocket, response: LoginResponse) {
+        const LOGIN_RESPONSE_SIZE: usize = 36;
+
+        let server_version: u32 = 134;
+        let subspace_checksum: u32 = 0;
+
+        let mut data = [0; LOGIN_RESPONSE_SIZE];
+
+        data[0] = 0x0A;
+        data[1] = response.code();
+        data[2..6].copy_from_slice(&server_version.to_le_bytes());
+        data[10..14].copy_from_slice(&subspace_checksum.to_le_bytes());
+
+        self.send_reliable_message(game_socket, &data);
+    }
+
     fn send_disconnect(&mut self, game_socket: &UdpSocket) {
         let packet = Packet::empty().concat_u8(0x00).concat_u8(0x07);
 
@@ -97,30 +163,29 @@ impl Connection {
         self.connected = false;
     }
 
-    fn send_enter_list(&mut self, player_manager: &PlayerManager, game_socket: &UdpSocket) {
-        const ENTER_PACKET_SIZE: usize = 64;
+    fn send_enter_list(
+        &mut self,
+        player_manager: &PlayerManager,
+        game_socket: &UdpSocket,
+        arena: &str,
+    ) {
+        // Leave room for the reliable header.
+        const MAX_ENTER_MESSAGE_SIZE: usize = MAX_PACKET_SIZE - 6;
 
         let mut packet = Packet::empty();
 
-        for (id, player) in &player_manager.players {
-            if packet.remaining() < ENTER_PACKET_SIZE {
+        for player in player_manager.players.values() {
+            if player.arena.as_deref() != Some(arena) {
+                continue;
+            }
+
+            if packet.size + ENTER_PACKET_SIZE > MAX_ENTER_MESSAGE_SIZE {
                 self.send_reliable_message(game_socket, &packet.data[..packet.size]);
 
                 packet = Packet::empty();
             }
 
-            let mut name_len = player.name.len();
-            if name_len > 20 {
-                name_len = 20;
-            }
-
-            let slice = &mut packet.data[packet.size..packet.size + ENTER_PACKET_SIZE];
-
-            slice[0] = 0x03;
-            slice[1] = 0x08;
-            slice[3..name_len + 3].copy_from_slice(&player.name.as_bytes()[..name_len]);
-            slice[51..53].copy_from_slice(&id.to_le_bytes());
-            packet.size += ENTER_PACKET_SIZE;
+            player.write_enter_packet(&mut packet);
         }
 
         if packet.size > 0 {
@@ -129,15 +194,34 @@ impl Connection {
     }
 }
 
-// TODO: Arenas
 struct Game {
+    config: Config,
+    security: SecurityConfig,
+    groups: Groups,
+    filter: FilterConfig,
+    accounts: AccountStore,
+    login: LoginConfig,
     player_manager: PlayerManager,
+    arena_manager: ArenaManager,
+    channels: ChannelStore,
+    chat: ChatConfig,
+    commands: CommandRegistry,
 }
 
 impl Game {
-    fn new() -> Self {
+    fn new(config: Config) -> Self {
         Self {
             player_manager: PlayerManager::new(),
+            arena_manager: ArenaManager::new(&config),
+            security: SecurityConfig::load(&config),
+            groups: Groups::load(&config),
+            filter: FilterConfig::load(&config),
+            accounts: AccountStore::load(ACCOUNT_PATH),
+            login: LoginConfig::load(&config),
+            channels: ChannelStore::load(CHANNEL_PATH),
+            chat: ChatConfig::load(&config),
+            commands: register_commands(),
+            config,
         }
     }
 
@@ -210,6 +294,7 @@ impl Game {
                         if let Some(conn) = connections.get_mut(&addr) {
                             conn.send_disconnect(game_socket);
                         }
+                        return false;
                     }
 
                     let id: u32 = u32::from_le_bytes(buf[2..6].try_into().unwrap());
@@ -229,6 +314,8 @@ impl Game {
                     let sync_response_packet = Packet::new_sync_response(Tick::new(recv_timestamp));
 
                     if let Some(conn) = connections.get_mut(&addr) {
+                        conn.time_offset = Some(Tick::now().diff(&Tick::new(recv_timestamp)));
+
                         if let Err(e) = conn.send(game_socket, sync_response_packet) {
                             println!("Error sending sync response: {}", e);
                         }
@@ -247,15 +334,10 @@ impl Game {
                     while !cluster.is_empty() {
                         let subsize: usize = cluster[0] as usize;
 
-                        if cluster.len() >= subsize + 1 {
+                        if cluster.len() > subsize {
                             let subpkt = &cluster[1..subsize + 1];
 
-                            self.handle_packet(
-                                game_socket,
-                                connections,
-                                addr,
-                                Packet::new(&subpkt),
-                            );
+                            self.handle_packet(game_socket, connections, addr, Packet::new(subpkt));
                         } else {
                             break;
                         }
@@ -269,266 +351,1706 @@ impl Game {
             match packet_type {
                 1 => {
                     // ArenaLogin
-                    let Some(conn) = connections.get_mut(&addr) else {
+                    return self.handle_arena_login(game_socket, connections, addr, buf);
+                }
+                3 => {
+                    // Position
+                    return self.handle_position(game_socket, connections, addr, buf);
+                }
+                5 => {
+                    // Death
+                    if buf.len() < 5 {
                         return false;
-                    };
+                    }
 
-                    let Some(player) = self.player_manager.get_player_by_id(conn.player_id) else {
-                        conn.send_disconnect(game_socket);
+                    let Some(conn) = connections.get(&addr) else {
                         return false;
                     };
 
-                    let mut pid_pkt = [0; 3];
-                    let pid: u16 = player.id;
+                    let victim = conn.player_id;
+                    let killer = u16::from_le_bytes(buf[1..3].try_into().unwrap());
+                    let bounty = u16::from_le_bytes(buf[3..5].try_into().unwrap());
 
-                    pid_pkt[0] = 0x01;
-                    pid_pkt[1..3].copy_from_slice(&pid.to_le_bytes());
-                    conn.send_reliable_message(game_socket, &pid_pkt);
+                    self.handle_death(game_socket, connections, victim, killer, bounty);
+                }
+                6 => {
+                    // Chat
+                    return self.handle_chat(game_socket, connections, addr, buf);
+                }
+                7 => {
+                    // Green pickup
+                    return self.handle_green(game_socket, connections, addr, buf);
+                }
+                8 => {
+                    // Spectate request
+                    if buf.len() < 3 {
+                        return false;
+                    }
 
-                    conn.send_small_chunked_message(game_socket, &ARENA_SETTINGS);
+                    let Some(conn) = connections.get(&addr) else {
+                        return false;
+                    };
 
-                    let mut map_info_pkt = [0; 25];
-                    let map_name = "pub.lvl";
-                    let map_checksum: u32 = 1889723958;
-                    let map_filesize: u32 = 58992;
+                    let target = u16::from_le_bytes(buf[1..3].try_into().unwrap());
 
-                    map_info_pkt[0] = 0x29;
-                    map_info_pkt[1..map_name.len() + 1].copy_from_slice(map_name.as_bytes());
-                    map_info_pkt[17..21].copy_from_slice(&map_checksum.to_le_bytes());
-                    map_info_pkt[21..25].copy_from_slice(&map_filesize.to_le_bytes());
+                    if let Some(player) = self.player_manager.get_player_by_id(conn.player_id) {
+                        player.spectating = match target {
+                            INVALID_PLAYER_ID => None,
+                            target => Some(target),
+                        };
+                    }
+                }
+                12 => {
+                    // Map request
+                    let Some(conn) = connections.get_mut(&addr) else {
+                        return false;
+                    };
 
-                    conn.send_reliable_message(game_socket, &map_info_pkt);
+                    let arena = self
+                        .player_manager
+                        .get_player_by_id(conn.player_id)
+                        .and_then(|player| player.arena.as_ref())
+                        .and_then(|arena| self.arena_manager.arenas.get(arena));
 
-                    conn.send_enter_list(&self.player_manager, game_socket);
+                    // The map is requested with only the type. Lvz files are requested by their index after the map.
+                    let index = match buf.len() {
+                        3.. => u16::from_le_bytes(buf[1..3].try_into().unwrap()) as usize,
+                        _ => 0,
+                    };
 
-                    let mut data = [0; 1];
-                    data[0] = 0x02;
-                    conn.send_reliable_message(game_socket, &data);
-                    self.broadcast_player_enter(game_socket, pid);
+                    match arena.and_then(|arena| arena.get_level_file(index)) {
+                        Some(file) => {
+                            println!("Sending {} to {:?}", file.filename, addr);
+                            conn.send_huge_chunked_message(&file.compressed);
+                        }
+                        None => println!(
+                            "File {} requested by {:?} but it isn't loaded.",
+                            index, addr
+                        ),
+                    }
                 }
-                36 => {
-                    // Password
-                    let Some(conn) = connections.get_mut(&addr) else {
+                15 => {
+                    // Freq change
+                    if buf.len() < 3 {
+                        return false;
+                    }
+
+                    let Some(conn) = connections.get(&addr) else {
                         return false;
                     };
-                    if buf.len() < 66 {
-                        conn.send_disconnect(game_socket);
+
+                    let freq = u16::from_le_bytes(buf[1..3].try_into().unwrap());
+                    let player_id = conn.player_id;
+                    self.handle_freq_change(game_socket, connections, player_id, freq);
+                }
+                24 => {
+                    // Ship change
+                    if buf.len() < 2 {
                         return false;
                     }
 
-                    let name = std::str::from_utf8(&buf[2..34]).unwrap();
-                    let _ = std::str::from_utf8(&buf[34..66]).unwrap(); // Password
+                    let Some(conn) = connections.get(&addr) else {
+                        return false;
+                    };
 
-                    println!("Name: {}", name);
+                    let player_id = conn.player_id;
+                    self.handle_ship_change(game_socket, connections, player_id, buf[1]);
+                }
+                9 | 36 => {
+                    // VIE and Continuum login
+                    return self.handle_login(game_socket, connections, addr, buf);
+                }
+                _ => {}
+            }
+        }
 
-                    if let Some(player) = self.player_manager.create_player(addr) {
-                        player.name = name.into();
+        if let Some(conn) = connections.get(&addr) {
+            return conn.connected;
+        }
+        false
+    }
 
-                        conn.player_id = player.id;
-                    } else {
-                        println!("Failed to create player for: {:?}", name);
-                        conn.send_disconnect(game_socket);
-                        return false;
-                    }
+    fn handle_login(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        addr: SocketAddr,
+        buf: &[u8],
+    ) -> bool {
+        let Some(conn) = connections.get_mut(&addr) else {
+            return false;
+        };
 
-                    // Send version packet
-                    let mut data = [0; MAX_PACKET_SIZE];
+        let Some(mut request) = LoginRequest::parse(buf) else {
+            println!("Received invalid login packet from {:?}", addr);
+            conn.send_login_response(game_socket, LoginResponse::NoPermission);
+            return conn.connected;
+        };
 
-                    let checksum: u32 = 0xC9B61486;
+        // The connection is already logged in.
+        if conn.player_id != INVALID_PLAYER_ID {
+            println!("Ignoring repeated login from {:?}", addr);
+            return conn.connected;
+        }
 
-                    data[0] = 0x34;
-                    data[1] = 40;
-                    data[2] = 0x00;
-                    data[3..7].copy_from_slice(&checksum.to_le_bytes());
+        let name = request.name.clone();
 
-                    let version_pkt = &data[..7];
-                    conn.send_reliable_message(game_socket, &version_pkt);
+        println!(
+            "Name: {} ({:?} version {}, machine id {})",
+            name, request.client, request.client_version, request.machine_id
+        );
 
-                    let server_version: u32 = 134;
-                    let subspace_checksum: u32 = 0;
+        let mut response = self.check_login(&name, &request.password, request.new_user);
 
-                    data[0] = 0x0A;
-                    data[1] = 0x00;
-                    data[2..6].copy_from_slice(&server_version.to_le_bytes());
-                    data[10..14].copy_from_slice(&subspace_checksum.to_le_bytes());
+        // Only look for an existing session once the password is known to be right so nobody else can kick it.
+        if response == LoginResponse::Ok
+            && let Some(existing) = self.player_manager.get_player_by_name(&name)
+        {
+            let existing = existing.id;
 
-                    let password_response_pkt = &data[..36];
-                    conn.send_reliable_message(game_socket, &password_response_pkt);
+            match self.login.duplicate_policy {
+                DuplicatePolicy::Kick => {
+                    println!("Kicking the existing session of {}", name);
+                    self.kick_duplicate(game_socket, connections, existing);
+                }
+                // The new connection isn't logged in so it can't get a chat message. No permission shows up as
+                // a permission error, which is the closest response to the name being in use.
+                DuplicatePolicy::Refuse => {
+                    println!("{} is already logged in", name);
+                    response = LoginResponse::NoPermission;
                 }
-                _ => {}
             }
         }
 
-        if let Some(conn) = connections.get(&addr) {
+        let Some(conn) = connections.get_mut(&addr) else {
+            return false;
+        };
+
+        if response != LoginResponse::Ok {
+            println!("Refusing login for {:?}: {:?}", name, response);
+            conn.send_login_response(game_socket, response);
             return conn.connected;
         }
-        return false;
-    }
 
-    fn broadcast_player_enter(&mut self, game_socket: &UdpSocket, player_id: PlayerId) {
-        const ENTER_PACKET_SIZE: usize = 64;
-        let mut packet = Packet::empty();
+        let Some(player) = self.player_manager.create_player(addr) else {
+            println!("No player ids left for {:?}", name);
+            conn.send_login_response(game_socket, LoginResponse::ServerFull);
+            return conn.connected;
+        };
 
-        let Some(join_player) = self.player_manager.get_player_by_id(player_id) else {
+        player.chat_channels = self.channels.get(&name);
+        player.group = self.groups.staff_group(&self.config, &name);
+        player.name = name;
+
+        request.password.clear();
+        let client = request.client;
+        player.login = Some(request);
+
+        conn.player_id = player.id;
+
+        // Send version packet. VIE clients don't know this packet.
+        if client == ClientKind::Continuum {
+            let mut data = [0; 7];
+
+            let checksum: u32 = 0xC9B61486;
+
+            data[0] = 0x34;
+            data[1] = 40;
+            data[2] = 0x00;
+            data[3..7].copy_from_slice(&checksum.to_le_bytes());
+
+            conn.send_reliable_message(game_socket, &data);
+        }
+        conn.send_login_response(game_socket, LoginResponse::Ok);
+
+        conn.connected
+    }
+
+    // Disconnects a session whose name logged in again from another connection.
+    fn kick_duplicate(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        player_id: PlayerId,
+    ) {
+        let Some(addr) = self.player_manager.players.get(&player_id).map(|p| p.addr) else {
             return;
         };
 
-        let mut name_len = join_player.name.len();
-        if name_len > 20 {
-            name_len = 20;
+        let message = "You have been disconnected because your name logged in from somewhere else.";
+        self.send_arena_message(game_socket, connections, player_id, message);
+
+        if let Some(mut conn) = connections.remove(&addr) {
+            conn.send_disconnect(game_socket);
         }
 
-        packet.data[0] = 0x03;
-        packet.data[1] = 0x08;
-        packet.data[3..name_len + 3].copy_from_slice(&join_player.name.as_bytes()[..name_len]);
-        packet.data[51..53].copy_from_slice(&join_player.id.to_le_bytes());
-        packet.size = ENTER_PACKET_SIZE;
+        self.broadcast_player_leave(game_socket, connections, player_id);
+    }
 
-        for (id, player) in self.player_manager.players.iter() {
-            if *id == player_id {
-                continue;
-            }
+    // Decides if the player can log in. Names without an account are registered here once the player confirms them.
+    fn check_login(&mut self, name: &str, password: &str, new_user: bool) -> LoginResponse {
+        if !self.login.accept_new_connections {
+            return LoginResponse::NoNewConnections;
+        }
 
-            if let Err(e) = game_socket.send_to(&packet.data[..packet.size], player.addr) {
-                println!("Error sending player enter: {}", e);
-            }
+        if let Some(violation) = self.filter.check_name(name) {
+            return match violation {
+                NameViolation::Offensive => LoginResponse::OffensiveName,
+                _ => LoginResponse::BadName,
+            };
         }
-    }
 
-    fn broadcast_player_leave(&mut self, game_socket: &UdpSocket, player_id: PlayerId) {
-        self.player_manager.remove_player(player_id);
+        // A session that gets kicked for this login frees its slot, so it doesn't count towards the limit.
+        let mut players = self.player_manager.players.len();
 
-        let packet = Packet::empty().concat_u8(0x04).concat_u16(player_id);
+        if self.login.duplicate_policy == DuplicatePolicy::Kick
+            && self.player_manager.get_player_by_name(name).is_some()
+        {
+            players -= 1;
+        }
+
+        if players >= self.login.max_players {
+            return LoginResponse::ServerFull;
+        }
+
+        if self.login.staff_only && self.groups.staff_group(&self.config, name) == DEFAULT_GROUP {
+            return LoginResponse::NoPermission2;
+        }
 
-        // TODO: Check arena and player in valid state to receive
-        for (_, player) in &self.player_manager.players {
-            if let Err(e) = game_socket.send_to(&packet.data[..packet.size], &player.addr) {
-                println!("Failed to send player leave: {}", e);
+        match self.accounts.get(name) {
+            Some(account) if account.banned => LoginResponse::LockedOut,
+            Some(account) if !account.check_password(password) => LoginResponse::BadPassword,
+            Some(_) => {
+                self.accounts.upgrade_hash(name, password);
+                LoginResponse::Ok
             }
+            // The client asks the player to confirm the new name and logs in again as a new user.
+            None if !new_user => LoginResponse::NewName,
+            None if !self.login.allow_new_accounts => LoginResponse::NoPermission,
+            None => match self.accounts.create(name, password) {
+                true => {
+                    println!("Registered new account {}", name);
+                    LoginResponse::Ok
+                }
+                false => LoginResponse::ServerBusy,
+            },
         }
     }
-}
 
-struct Server {
-    ping_socket: UdpSocket,
-    game_socket: UdpSocket,
+    fn update(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+    ) {
+        let score_resets = self.arena_manager.update(Tick::now());
 
-    connections: HashMap<SocketAddr, Connection>,
-    game: Game,
-}
+        for arena in score_resets {
+            self.reset_scores(game_socket, connections, &arena);
+        }
+    }
 
-impl Server {
-    fn new(port: u16) -> Result<Self, std::io::Error> {
-        let game_socket = UdpSocket::bind(format!("0.0.0.0:{}", port))?;
-        let ping_socket = UdpSocket::bind(format!("0.0.0.0:{}", port + 1))?;
+    // Resets the score of every player in the arena and tells the arena.
+    fn reset_scores(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        arena: &str,
+    ) {
+        println!("Resetting scores in arena {}", arena);
 
-        game_socket.set_nonblocking(true)?;
-        ping_socket.set_nonblocking(true)?;
+        let mut reset_ids = vec![];
 
-        Ok(Self {
-            ping_socket,
-            game_socket,
-            connections: HashMap::new(),
-            game: Game::new(),
-        })
+        for player in self.player_manager.players.values_mut() {
+            if player.arena.as_deref() == Some(arena) {
+                player.score = Score::default();
+                reset_ids.push(player.id);
+            }
+        }
+
+        for pid in reset_ids {
+            let packet = Packet::empty().concat_u8(0x1A).concat_u16(pid);
+
+            self.send_reliable_to_arena(game_socket, connections, arena, &packet);
+        }
     }
 
-    fn remove_connection(&mut self, addr: SocketAddr) {
-        let Some(player_id) = self
-            .connections
-            .get(&addr)
-            .and_then(|conn| Some(conn.player_id))
-        else {
+    // Sends the player's current score to everyone in their arena.
+    fn send_score_update(
+        &self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        pid: PlayerId,
+    ) {
+        let Some(player) = self.player_manager.players.get(&pid) else {
             return;
         };
 
-        if let Some(conn) = self.connections.remove(&addr).as_mut() {
-            conn.send_disconnect(&mut self.game_socket);
-        }
+        let Some(arena) = &player.arena else {
+            return;
+        };
 
-        self.game
-            .broadcast_player_leave(&self.game_socket, player_id);
+        self.send_reliable_to_arena(game_socket, connections, arena, &player.score_packet());
     }
 
-    fn timeout_connection(&mut self) {
-        const TIMEOUT_TICKS: i32 = 1000;
-        let mut remove_addr = None;
+    fn handle_arena_login(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        addr: SocketAddr,
+        buf: &[u8],
+    ) -> bool {
+        const ARENA_LOGIN_SIZE: usize = 26;
 
-        let now = Tick::now();
+        let Some(conn) = connections.get_mut(&addr) else {
+            return false;
+        };
 
-        // Times out the first connection that hasn't sent data recently.
-        for (addr, connection) in &mut self.connections {
-            let ticks_since_data = now.diff(&connection.last_packet_time);
+        if buf.len() < ARENA_LOGIN_SIZE {
+            conn.send_disconnect(game_socket);
+            return false;
+        }
 
-            if ticks_since_data >= TIMEOUT_TICKS {
-                println!("Timing out {:?}", addr);
-                remove_addr = Some(addr.to_owned());
-                break;
-            }
+        let pid = conn.player_id;
+
+        if self.player_manager.get_player_by_id(pid).is_none() {
+            conn.send_disconnect(game_socket);
+            return false;
         }
 
-        if let Some(addr) = remove_addr {
-            self.remove_connection(addr);
+        let arena_type = u16::from_le_bytes(buf[8..10].try_into().unwrap());
+        let request = ArenaRequest::new(arena_type, &read_fixed_string(&buf[10..26]));
+
+        // Leave the current arena before entering the new one.
+        self.leave_arena(game_socket, connections, pid);
+
+        let player_manager = &self.player_manager;
+        let arena_name = self
+            .arena_manager
+            .resolve(&request, |name| player_manager.arena_population(name));
+
+        let score = match self.player_manager.players.get(&pid) {
+            Some(player) => self.arena_manager.take_score(&arena_name, &player.name),
+            None => Score::default(),
+        };
+
+        let arena = self.arena_manager.get_or_create(&arena_name, &self.config);
+        let map_info_pkt = arena.map_info_packet();
+        let security_pkt = arena.security_packet();
+        let settings = arena.settings.data;
+
+        let spectator_freq = arena.config.spectator_freq;
+
+        if let Some(player) = self.player_manager.get_player_by_id(pid) {
+            println!("{} entering arena {}", player.name, arena_name);
+            player.arena = Some(arena_name.clone());
+            player.ship = SHIP_SPECTATOR;
+            player.freq = spectator_freq;
+            player.score = score;
+            player.accept_audio = u16::from_le_bytes(buf[2..4].try_into().unwrap()) != 0;
         }
-    }
 
-    fn poll_game(&mut self) -> std::io::Result<()> {
-        self.timeout_connection();
+        let Some(conn) = connections.get_mut(&addr) else {
+            return false;
+        };
 
-        let mut buf = [0; MAX_PACKET_SIZE];
-        let (size, src) = match self.game_socket.recv_from(&mut buf) {
-            Ok(r) => Ok((r.0, r.1)),
-            Err(e) => {
-                if e.kind() == std::io::ErrorKind::WouldBlock {
-                    return Ok(());
-                }
-                Err(e)
-            }
-        }?;
+        let mut pid_pkt = [0; 3];
 
-        let buf = &buf[..size];
+        pid_pkt[0] = 0x01;
+        pid_pkt[1..3].copy_from_slice(&pid.to_le_bytes());
+        conn.send_reliable_message(game_socket, &pid_pkt);
 
-        let connection = match self.connections.get_mut(&src) {
-            Some(connection) => connection,
-            None => {
-                let pkt_type = buf[0];
-                if pkt_type == 0x00 && size >= 8 && buf[1] == 0x01 {
-                    let key: u32 = u32::from_le_bytes(buf[2..6].try_into().unwrap()); // Key
-                    let _: u16 = u16::from_le_bytes(buf[6..8].try_into().unwrap()); // Version
+        conn.send_small_chunked_message(game_socket, &settings);
+        conn.send_reliable_message(game_socket, &security_pkt.data[..security_pkt.size]);
 
-                    let mut response = [0; 7];
-                    response[0] = 0x00;
-                    response[1] = 0x02;
-                    response[2..6].copy_from_slice(&key.to_le_bytes()); // Send key back to disable encryption
-                    response[6] = 0x00; // No billing
+        conn.send_reliable_message(game_socket, &map_info_pkt.data[..map_info_pkt.size]);
 
-                    self.game_socket.send_to(&response, &src)?;
+        conn.send_enter_list(&self.player_manager, game_socket, &arena_name);
 
-                    self.connections.insert(src, Connection::new(src));
-                    println!("Adding new connection");
-                }
-                return Ok(());
-            }
+        let data = [0x02];
+        conn.send_reliable_message(game_socket, &data);
+        let connected = conn.connected;
+
+        self.broadcast_player_enter(game_socket, connections, pid);
+
+        connected
+    }
+
+    // Stores the player's new position and sends it to everyone else in the arena.
+    fn handle_position(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        addr: SocketAddr,
+        buf: &[u8],
+    ) -> bool {
+        let Some(conn) = connections.get_mut(&addr) else {
+            return false;
         };
 
-        println!("Recv: {:?}", buf);
+        let Some(position) = PositionPacket::parse(buf) else {
+            println!("Received invalid position packet from {:?}", addr);
+            return conn.connected;
+        };
 
-        connection.last_packet_time = Tick::now();
+        let pid = conn.player_id;
+        let now = Tick::now();
+        let server_time = conn.to_server_time(position.timestamp).unwrap_or(now);
 
-        let addr = connection.addr.clone();
+        let Some(player) = self.player_manager.get_player_by_id(pid) else {
+            return conn.connected;
+        };
 
-        if !self.game.on_data(
-            &self.game_socket,
-            &mut self.connections,
-            addr,
-            Packet::new(buf),
-        ) {
-            self.remove_connection(addr);
+        let Some(arena) = player.arena.clone() else {
+            return conn.connected;
+        };
+
+        let ship = player.ship;
+
+        if let Some(violation) = self.validate_position(buf, &position, server_time, &arena, ship) {
+            println!("Position violation {:?} from {:?}", violation, addr);
+
+            match self.security.position_policy {
+                ViolationPolicy::Drop => {}
+                ViolationPolicy::Spec => self.force_spectator(game_socket, connections, pid),
+                ViolationPolicy::Kick => conn.send_disconnect(game_socket),
+            }
+
+            return connections.get(&addr).is_some_and(|conn| conn.connected);
+        }
+
+        let Some(player) = self.player_manager.get_player_by_id(pid) else {
+            return conn.connected;
+        };
+
+        player.x = position.x;
+        player.y = position.y;
+        player.x_velocity = position.x_velocity;
+        player.y_velocity = position.y_velocity;
+        player.rotation = position.rotation;
+        player.status = position.status;
+        player.bounty = position.bounty;
+        player.energy = position.energy;
+
+        // Spectator positions are only kept for position culling.
+        if ship == SHIP_SPECTATOR {
+            return conn.connected;
+        }
+
+        let timestamp = server_time.value() as u16;
+        let latency = now.diff(&server_time).clamp(0, u8::MAX as i32) as u8;
+
+        let build = |include_extra| match position.fits_small_packet(pid) {
+            true => position.build_small(pid, timestamp, latency, include_extra),
+            false => position.build_weapon(pid, timestamp, latency, include_extra),
+        };
+
+        let packet = build(false);
+        let extra_packet = build(true);
+
+        let Some(arena_config) = self
+            .arena_manager
+            .arenas
+            .get(&arena)
+            .map(|arena| &arena.config)
+        else {
+            return conn.connected;
+        };
+
+        let mut receivers = Vec::new();
+
+        for (id, player) in &self.player_manager.players {
+            if *id == pid || player.arena.as_ref() != Some(&arena) {
+                continue;
+            }
+
+            // Spectators see from the position of the player they are watching.
+            let (x, y) = match player
+                .spectating
+                .and_then(|target| self.player_manager.players.get(&target))
+            {
+                Some(target) => (target.x, target.y),
+                None => (player.x, player.y),
+            };
+
+            let dx = x as i64 - position.x as i64;
+            let dy = y as i64 - position.y as i64;
+            let distance = ((dx * dx + dy * dy) as f64).sqrt() as u32;
+            let delay = arena_config.position_delay(distance);
+
+            // Weapons are always sent so nobody gets hit by a bullet they never saw.
+            let send = position.weapon != 0
+                || delay <= 0
+                || player
+                    .last_position_sent
+                    .get(&pid)
+                    .is_none_or(|last_sent| now.diff(last_sent) >= delay);
+
+            // The extra data has the sender's energy, timers and items, so only players watching the sender or staff
+            // that can see it get it.
+            let include_extra =
+                player.spectating == Some(pid) || self.has_capability(player, "seeepd");
+
+            if send {
+                receivers.push((*id, include_extra));
+            }
+        }
+
+        for (id, include_extra) in receivers {
+            let Some(player) = self.player_manager.players.get_mut(&id) else {
+                continue;
+            };
+
+            let packet = match include_extra {
+                true => &extra_packet,
+                false => &packet,
+            };
+
+            if let Err(e) = game_socket.send_to(&packet.data[..packet.size], player.addr) {
+                println!("Failed to send position: {}", e);
+            }
+
+            player.last_position_sent.insert(pid, now);
+        }
+
+        conn.connected
+    }
+
+    fn validate_position(
+        &self,
+        buf: &[u8],
+        position: &PositionPacket,
+        server_time: Tick,
+        arena: &str,
+        ship: u8,
+    ) -> Option<PositionViolation> {
+        if !PositionPacket::verify_checksum(buf) {
+            return Some(PositionViolation::Checksum);
+        }
+
+        if Tick::now().diff(&server_time).abs() > self.security.max_timestamp_drift {
+            return Some(PositionViolation::Timestamp);
+        }
+
+        // Spectators only send their camera position.
+        if ship == SHIP_SPECTATOR {
+            return None;
+        }
+
+        let settings = &self.arena_manager.arenas.get(arena)?.settings;
+
+        let fastest_speed = settings.ship_fastest_speed(ship);
+        let speed_limit = fastest_speed as u64 * self.security.speed_tolerance.max(0) as u64 / 100;
+
+        if position.speed() as u64 > speed_limit {
+            return Some(PositionViolation::Speed);
+        }
+
+        if position.energy > settings.ship_maximum_energy(ship) {
+            return Some(PositionViolation::Energy);
+        }
+
+        None
+    }
+
+    // Awards the kill and tells the arena. The victim reports its own death along with its bounty at the time.
+    fn handle_death(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        victim_id: PlayerId,
+        killer_id: PlayerId,
+        bounty: u16,
+    ) {
+        if victim_id == killer_id {
+            return;
+        }
+
+        let (Some(victim), Some(killer)) = (
+            self.player_manager.players.get(&victim_id),
+            self.player_manager.players.get(&killer_id),
+        ) else {
+            println!("Death from {} with invalid killer {}", victim_id, killer_id);
+            return;
+        };
+
+        let Some(arena_name) = victim.arena.clone() else {
+            return;
+        };
+
+        if killer.arena.as_ref() != Some(&arena_name) {
+            println!(
+                "Death from {} with killer {} in another arena",
+                victim_id, killer_id
+            );
+            return;
+        }
+
+        if victim.ship == SHIP_SPECTATOR || killer.ship == SHIP_SPECTATOR {
+            return;
+        }
+
+        if victim.freq == killer.freq {
+            println!("Death from {} with teammate {}", victim_id, killer_id);
+            return;
+        }
+
+        let Some(arena) = self.arena_manager.arenas.get(&arena_name) else {
+            return;
+        };
+
+        let flags = victim.flags_carried;
+        let mut points = bounty as u32;
+
+        // The client gives the extra points to a killer that is carrying flags, counted before it picks up the
+        // victim's flags.
+        if killer.flags_carried > 0 {
+            points *= 1 + arena.settings.flagger_kill_multiplier();
+        }
+
+        let bounty_increase = arena.settings.bounty_increase_for_kill();
+
+        if let Some(victim) = self.player_manager.players.get_mut(&victim_id) {
+            victim.score.losses = victim.score.losses.saturating_add(1);
+            victim.bounty = 0;
+            victim.flags_carried = 0;
+        }
+
+        if let Some(killer) = self.player_manager.players.get_mut(&killer_id) {
+            killer.score.wins = killer.score.wins.saturating_add(1);
+            killer.score.kill_points = killer.score.kill_points.saturating_add(points);
+            killer.bounty = killer.bounty.saturating_add(bounty_increase);
+            killer.flags_carried = killer.flags_carried.saturating_add(flags);
+        }
+
+        // The victim drops a prize where they died.
+        let green = match self.arena_manager.arenas.get_mut(&arena_name) {
+            Some(arena) => arena.random_prize(),
+            None => 0,
+        };
+
+        let packet = Packet::empty()
+            .concat_u8(0x06)
+            .concat_u8(green)
+            .concat_u16(killer_id)
+            .concat_u16(victim_id)
+            .concat_u16(points.min(u16::MAX as u32) as u16)
+            .concat_u16(flags);
+
+        self.send_reliable_to_arena(game_socket, connections, &arena_name, &packet);
+        self.send_score_update(game_socket, connections, killer_id);
+        self.send_score_update(game_socket, connections, victim_id);
+    }
+
+    // Routes a chat message to everyone that should receive it based on its type.
+    fn handle_chat(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        addr: SocketAddr,
+        buf: &[u8],
+    ) -> bool {
+        let Some(conn) = connections.get(&addr) else {
+            return false;
+        };
+
+        let connected = conn.connected;
+        let pid = conn.player_id;
+
+        let Some(message) = ChatMessage::parse(buf) else {
+            println!("Received invalid chat packet from {:?}", addr);
+            return connected;
+        };
+
+        let Some(sender) = self.player_manager.players.get(&pid) else {
+            return connected;
+        };
+
+        let Some(arena) = sender.arena.clone() else {
+            return connected;
+        };
+
+        let sender_name = sender.name.clone();
+        let sender_freq = sender.freq;
+        let bypass_limits = self.has_capability(sender, "bypasschatlimits");
+        let text = message.text.as_str();
+
+        if !self.check_chat_limits(game_socket, connections, pid) {
+            return connected;
+        }
+
+        match message.kind {
+            ChatType::Public | ChatType::PublicMacro => {
+                if is_command(text) {
+                    let target = CommandTarget::Arena;
+                    self.handle_command(game_socket, connections, pid, target, text);
+                    return connected;
+                }
+
+                if let Some(text) = text.strip_prefix(MOD_CHAT_PREFIX) {
+                    self.send_mod_chat(game_socket, connections, pid, text);
+                    return connected;
+                }
+
+                let public_chat = self
+                    .arena_manager
+                    .arenas
+                    .get(&arena)
+                    .is_none_or(|arena| arena.public_chat);
+
+                if !public_chat && !bypass_limits {
+                    let message = "Public chat is disabled in this arena.";
+                    self.send_arena_message(game_socket, connections, pid, message);
+                    return connected;
+                }
+
+                let text = match self.filter.filter_public_chat {
+                    true => self.filter.mask_words(text),
+                    false => text.to_owned(),
+                };
+
+                let packet = build_chat_packet(message.kind, message.sound, pid, &text);
+
+                self.send_chat(game_socket, connections, &packet, |player| {
+                    player.id != pid && player.arena.as_ref() == Some(&arena)
+                });
+            }
+            ChatType::Team => {
+                if is_command(text) {
+                    let target = CommandTarget::Freq(sender_freq);
+                    self.handle_command(game_socket, connections, pid, target, text);
+                    return connected;
+                }
+
+                let packet = build_chat_packet(message.kind, message.sound, pid, text);
+
+                self.send_chat(game_socket, connections, &packet, |player| {
+                    player.id != pid
+                        && player.arena.as_ref() == Some(&arena)
+                        && player.freq == sender_freq
+                });
+            }
+            ChatType::Frequency => {
+                let Some(target) = self.player_manager.players.get(&message.target) else {
+                    return connected;
+                };
+
+                if target.arena.as_ref() != Some(&arena) {
+                    return connected;
+                }
+
+                let freq = target.freq;
+
+                if is_command(text) {
+                    let target = CommandTarget::Freq(freq);
+                    self.handle_command(game_socket, connections, pid, target, text);
+                    return connected;
+                }
+
+                let packet = build_chat_packet(message.kind, message.sound, pid, text);
+
+                self.send_chat(game_socket, connections, &packet, |player| {
+                    player.id != pid && player.arena.as_ref() == Some(&arena) && player.freq == freq
+                });
+            }
+            ChatType::Private => {
+                let target_in_arena = self
+                    .player_manager
+                    .players
+                    .get(&message.target)
+                    .is_some_and(|target| target.arena.as_ref() == Some(&arena));
+
+                if !target_in_arena {
+                    return connected;
+                }
+
+                if is_command(text) {
+                    let target = CommandTarget::Player(message.target);
+                    self.handle_command(game_socket, connections, pid, target, text);
+                    return connected;
+                }
+
+                let packet = build_chat_packet(message.kind, message.sound, pid, text);
+
+                self.send_chat(game_socket, connections, &packet, |player| {
+                    player.id == message.target
+                        && player.id != pid
+                        && player.arena.as_ref() == Some(&arena)
+                });
+            }
+            ChatType::RemotePrivate => {
+                let Some((name, text)) = parse_remote_private(text) else {
+                    return connected;
+                };
+
+                let Some(target) = self.player_manager.get_player_by_name(name) else {
+                    return connected;
+                };
+
+                let target_id = target.id;
+
+                if is_command(text) {
+                    let target = CommandTarget::Player(target_id);
+                    self.handle_command(game_socket, connections, pid, target, text);
+                    return connected;
+                }
+
+                // Players in the same arena get a normal private message so the client shows the sender.
+                let packet = match target.arena.as_ref() == Some(&arena) {
+                    true => build_chat_packet(ChatType::Private, message.sound, pid, text),
+                    false => build_chat_packet(
+                        ChatType::RemotePrivate,
+                        message.sound,
+                        INVALID_PLAYER_ID,
+                        &format!("({})>{}", sender_name, text),
+                    ),
+                };
+
+                self.send_chat(game_socket, connections, &packet, |player| {
+                    player.id == target_id && player.id != pid
+                });
+            }
+            ChatType::Channel => {
+                self.send_channel_chat(game_socket, connections, pid, text);
+            }
+            ChatType::Arena | ChatType::Warning | ChatType::SysopWarning => {
+                println!("{} tried to send a server only chat message.", sender_name);
+            }
+        }
+
+        connected
+    }
+
+    // Checks if the player is allowed to chat right now. Players that send too many messages within the flood window
+    // are muted for a while. Returns false if the message should be dropped.
+    fn check_chat_limits(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        pid: PlayerId,
+    ) -> bool {
+        let now = Tick::now();
+
+        let Some(player) = self.player_manager.players.get(&pid) else {
+            return false;
+        };
+
+        if self.has_capability(player, "bypasschatlimits") {
+            return true;
+        }
+
+        let Some(player) = self.player_manager.players.get_mut(&pid) else {
+            return false;
+        };
+
+        if let Some(muted_until) = player.muted_until {
+            if muted_until.gt(&now) {
+                let seconds = muted_until.diff(&now) / TICKS_PER_SECOND + 1;
+                let message = format!("You are muted for {} more seconds.", seconds);
+                self.send_arena_message(game_socket, connections, pid, &message);
+                return false;
+            }
+
+            player.muted_until = None;
+        }
+
+        while let Some(time) = player.chat_times.front() {
+            if now.diff(time) < self.chat.flood_window {
+                break;
+            }
+
+            player.chat_times.pop_front();
+        }
+
+        player.chat_times.push_back(now);
+
+        if player.chat_times.len() <= self.chat.flood_limit {
+            return true;
+        }
+
+        println!("Muting {} for flooding chat", player.name);
+
+        let mute_time = self.chat.flood_mute_time;
+
+        player.chat_times.clear();
+        player.muted_until = Some(mute_end(now, mute_time));
+
+        let message = format!(
+            "You have been muted for {} seconds for flooding.",
+            mute_time
+        );
+        self.send_arena_message(game_socket, connections, pid, &message);
+
+        false
+    }
+
+    // Sends the message to everyone on the channel in any arena. Each receiver sees the channel by their own number.
+    fn send_channel_chat(
+        &self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        pid: PlayerId,
+        text: &str,
+    ) {
+        let Some(sender) = self.player_manager.players.get(&pid) else {
+            return;
+        };
+
+        let (index, text) = parse_channel_message(text);
+
+        let Some(channel) = sender.chat_channels.get(index) else {
+            let message = format!("You are not on chat channel {}.", index + 1);
+            self.send_arena_message(game_socket, connections, pid, &message);
+            return;
+        };
+
+        for player in self.player_manager.players.values() {
+            if player.id == pid {
+                continue;
+            }
+
+            let Some(number) = player.chat_channels.iter().position(|c| c == channel) else {
+                continue;
+            };
+
+            let Some(conn) = connections.get_mut(&player.addr) else {
+                continue;
+            };
+
+            let text = format!("{}:{}> {}", number + 1, sender.name, text);
+            let packet = build_chat_packet(ChatType::Channel, 0, INVALID_PLAYER_ID, &text);
+
+            conn.send_reliable_message(game_socket, &packet.data[..packet.size]);
+        }
+    }
+
+    // Sends a green arena message to a single player.
+    fn send_arena_message(
+        &self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        pid: PlayerId,
+        text: &str,
+    ) {
+        let packet = build_chat_packet(ChatType::Arena, 0, INVALID_PLAYER_ID, text);
+
+        self.send_chat(game_socket, connections, &packet, |player| player.id == pid);
+    }
+
+    // Sends moderator chat to every moderator on the server. It's shown as an arena message with the sender's name.
+    fn send_mod_chat(
+        &self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        pid: PlayerId,
+        text: &str,
+    ) {
+        let Some(sender) = self.player_manager.players.get(&pid) else {
+            return;
+        };
+
+        if !self.has_capability(sender, "seemodchat") {
+            return;
+        }
+
+        let text = format!("{}> {}", sender.name, text);
+        let packet = build_chat_packet(ChatType::Arena, 0, INVALID_PLAYER_ID, &text);
+
+        self.send_chat(game_socket, connections, &packet, |player| {
+            player.id != pid && self.has_capability(player, "seemodchat")
+        });
+    }
+
+    fn has_capability(&self, player: &Player, capability: &str) -> bool {
+        self.groups.has_capability(&player.group, capability)
+    }
+
+    // Checks if the player has the capability the command requires.
+    fn can_use_command(&self, player: &Player, command: &Command) -> bool {
+        match command.capability {
+            Some(capability) => self.has_capability(player, capability),
+            None => true,
+        }
+    }
+
+    // Runs a command that the player sent in a chat message. Problems are sent back to the player as arena messages.
+    fn handle_command(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        pid: PlayerId,
+        target: CommandTarget,
+        text: &str,
+    ) {
+        let Some((name, args)) = parse_command(text) else {
+            return;
+        };
+
+        let Some(player) = self.player_manager.players.get(&pid) else {
+            return;
+        };
+
+        // Commands the player can't use are treated as unknown so they don't reveal what staff can do.
+        let command = match self.commands.find(name) {
+            Some(command) if self.can_use_command(player, &command) => command,
+            _ => {
+                let message = format!("Unknown command: {}", name);
+                self.send_arena_message(game_socket, connections, pid, &message);
+                return;
+            }
+        };
+
+        if !command.accepts(target) {
+            let message = format!(
+                "{}{} can't be used on that target.",
+                command.prefix(),
+                command.name
+            );
+            self.send_arena_message(game_socket, connections, pid, &message);
+            return;
+        }
+
+        println!(
+            "{} used command {} {}",
+            player.name,
+            command.name,
+            args.unwrap_or_default()
+        );
+
+        let context = CommandContext {
+            sender: pid,
+            target,
+            args,
+        };
+
+        (command.handler)(self, game_socket, connections, &context);
+    }
+
+    // Sends the chat packet reliably to every player that passes the filter.
+    fn send_chat<F>(
+        &self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        packet: &Packet,
+        filter: F,
+    ) where
+        F: Fn(&Player) -> bool,
+    {
+        for player in self.player_manager.players.values() {
+            if !filter(player) {
+                continue;
+            }
+
+            if let Some(conn) = connections.get_mut(&player.addr) {
+                conn.send_reliable_message(game_socket, &packet.data[..packet.size]);
+            }
+        }
+    }
+
+    // Records the green and shares it with the player's teammates. The client decides if a shared prize applies to it.
+    fn handle_green(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        addr: SocketAddr,
+        buf: &[u8],
+    ) -> bool {
+        const GREEN_SIZE: usize = 11;
+
+        let Some(conn) = connections.get(&addr) else {
+            return false;
+        };
+
+        if buf.len() < GREEN_SIZE {
+            return conn.connected;
+        }
+
+        let pid = conn.player_id;
+        let connected = conn.connected;
+        let timestamp = u32::from_le_bytes(buf[1..5].try_into().unwrap());
+        let timestamp = conn.to_server_time(timestamp).unwrap_or_else(Tick::now);
+        let x = u16::from_le_bytes(buf[5..7].try_into().unwrap());
+        let y = u16::from_le_bytes(buf[7..9].try_into().unwrap());
+        let prize = i16::from_le_bytes(buf[9..11].try_into().unwrap());
+
+        let Some(player) = self.player_manager.get_player_by_id(pid) else {
+            return connected;
+        };
+
+        if player.ship == SHIP_SPECTATOR {
+            return connected;
+        }
+
+        let Some(arena) = player.arena.clone() else {
+            return connected;
+        };
+
+        player.greens_picked_up = player.greens_picked_up.saturating_add(1);
+
+        let freq = player.freq;
+        let packet = Packet::empty()
+            .concat_u8(0x08)
+            .concat_u32(timestamp.value())
+            .concat_u16(x)
+            .concat_u16(y)
+            .concat_i16(prize)
+            .concat_u16(pid);
+
+        for player in self.player_manager.players.values() {
+            if player.id == pid
+                || player.arena.as_ref() != Some(&arena)
+                || player.freq != freq
+                || player.ship == SHIP_SPECTATOR
+            {
+                continue;
+            }
+
+            if let Some(conn) = connections.get_mut(&player.addr) {
+                conn.send_reliable_message(game_socket, &packet.data[..packet.size]);
+            }
+        }
+
+        connected
+    }
+
+    // Gives the prize to the target. A negative prize takes it away instead. Freq targets are in the arena.
+    fn grant_prize(
+        &self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        arena: &str,
+        target: PrizeTarget,
+        prize: i16,
+        count: i16,
+    ) {
+        let packet = Packet::empty()
+            .concat_u8(0x20)
+            .concat_i16(count)
+            .concat_i16(prize);
+
+        for player in self.player_manager.players.values() {
+            if player.arena.as_deref() != Some(arena) || player.ship == SHIP_SPECTATOR {
+                continue;
+            }
+
+            let matches = match target {
+                PrizeTarget::Player(pid) => player.id == pid,
+                PrizeTarget::Freq(freq) => player.freq == freq,
+            };
+
+            if !matches {
+                continue;
+            }
+
+            if let Some(conn) = connections.get_mut(&player.addr) {
+                conn.send_reliable_message(game_socket, &packet.data[..packet.size]);
+            }
+        }
+    }
+
+    // Moves the player into spectator mode and tells everyone in their arena.
+    fn force_spectator(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        player_id: PlayerId,
+    ) {
+        let Some(spectator_freq) = self
+            .player_manager
+            .get_player_by_id(player_id)
+            .and_then(|player| player.arena.as_ref())
+            .and_then(|arena| self.arena_manager.arenas.get(arena))
+            .map(|arena| arena.config.spectator_freq)
+        else {
+            return;
+        };
+
+        self.set_ship_and_freq(
+            game_socket,
+            connections,
+            player_id,
+            SHIP_SPECTATOR,
+            spectator_freq,
+        );
+    }
+
+    // Finds the public freq with the fewest players in ships that still has room.
+    fn balanced_freq(&self, arena_name: &str) -> Option<u16> {
+        let arena = self.arena_manager.arenas.get(arena_name)?;
+
+        (0..arena.config.public_freqs)
+            .map(|freq| (freq, self.player_manager.freq_population(arena_name, freq)))
+            .filter(|(freq, population)| arena.can_join_freq(*freq, *population))
+            .min_by_key(|(_, population)| *population)
+            .map(|(freq, _)| freq)
+    }
+
+    fn handle_ship_change(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        player_id: PlayerId,
+        ship: u8,
+    ) {
+        if ship > SHIP_SPECTATOR {
+            return;
+        }
+
+        let Some(player) = self.player_manager.get_player_by_id(player_id) else {
+            return;
+        };
+
+        let (current_ship, current_freq) = (player.ship, player.freq);
+
+        if ship == current_ship {
+            return;
+        }
+
+        let Some(arena_name) = player.arena.clone() else {
+            return;
+        };
+
+        let Some(arena) = self.arena_manager.arenas.get(&arena_name) else {
+            return;
+        };
+
+        if !arena.is_ship_allowed(ship) {
+            println!("Denied ship {} for player {}", ship, player_id);
+            self.send_ship_state(game_socket, connections, player_id);
+            return;
+        }
+
+        if ship == SHIP_SPECTATOR {
+            let spectator_freq = arena.config.spectator_freq;
+            self.set_ship_and_freq(game_socket, connections, player_id, ship, spectator_freq);
+            return;
+        }
+
+        let mut freq = Some(current_freq);
+
+        // Players coming out of spectator mode aren't counted on their freq yet, so make sure there's room.
+        if current_ship == SHIP_SPECTATOR {
+            let population = self
+                .player_manager
+                .freq_population(&arena_name, current_freq);
+
+            if !arena.can_join_freq(current_freq, population) {
+                freq = self.balanced_freq(&arena_name);
+            }
+        }
+
+        match freq {
+            Some(freq) => self.set_ship_and_freq(game_socket, connections, player_id, ship, freq),
+            None => {
+                println!("No freq available for player {}", player_id);
+                self.send_ship_state(game_socket, connections, player_id);
+            }
+        }
+    }
+
+    fn handle_freq_change(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        player_id: PlayerId,
+        freq: u16,
+    ) {
+        let Some(player) = self.player_manager.get_player_by_id(player_id) else {
+            return;
+        };
+
+        let (ship, current_freq) = (player.ship, player.freq);
+
+        if freq == current_freq {
+            return;
+        }
+
+        let Some(arena_name) = player.arena.clone() else {
+            return;
+        };
+
+        let Some(arena) = self.arena_manager.arenas.get(&arena_name) else {
+            return;
+        };
+
+        if ship == SHIP_SPECTATOR {
+            if arena.is_freq_valid(freq) {
+                self.set_freq(game_socket, connections, player_id, freq);
+            } else {
+                self.send_ship_state(game_socket, connections, player_id);
+            }
+            return;
+        }
+
+        let population = self.player_manager.freq_population(&arena_name, freq);
+
+        if arena.can_join_freq(freq, population) {
+            self.set_ship_and_freq(game_socket, connections, player_id, ship, freq);
+        } else {
+            println!("Denied freq {} for player {}", freq, player_id);
+            self.send_ship_state(game_socket, connections, player_id);
+        }
+    }
+
+    // Sets the ship and freq without checking the arena rules and tells everyone in the arena.
+    fn set_ship_and_freq(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        player_id: PlayerId,
+        ship: u8,
+        freq: u16,
+    ) {
+        let Some(player) = self.player_manager.get_player_by_id(player_id) else {
+            return;
+        };
+
+        player.ship = ship;
+        player.freq = freq;
+        player.bounty = 0;
+
+        let Some(arena) = player.arena.clone() else {
+            return;
+        };
+
+        let packet = Packet::empty()
+            .concat_u8(0x1D)
+            .concat_u8(ship)
+            .concat_u16(player_id)
+            .concat_u16(freq);
+
+        self.send_reliable_to_arena(game_socket, connections, &arena, &packet);
+    }
+
+    // Changes the freq of a spectator and tells everyone in the arena.
+    fn set_freq(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        player_id: PlayerId,
+        freq: u16,
+    ) {
+        let Some(player) = self.player_manager.get_player_by_id(player_id) else {
+            return;
+        };
+
+        player.freq = freq;
+
+        let Some(arena) = player.arena.clone() else {
+            return;
+        };
+
+        let packet = Packet::empty()
+            .concat_u8(0x0D)
+            .concat_u16(player_id)
+            .concat_u16(freq)
+            .concat_u8(0xFF);
+
+        self.send_reliable_to_arena(game_socket, connections, &arena, &packet);
+    }
+
+    // Resends the player's current ship and freq to them after a denied change so the client goes back to it.
+    fn send_ship_state(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        player_id: PlayerId,
+    ) {
+        let Some(player) = self.player_manager.get_player_by_id(player_id) else {
+            return;
+        };
+
+        let packet = Packet::empty()
+            .concat_u8(0x1D)
+            .concat_u8(player.ship)
+            .concat_u16(player_id)
+            .concat_u16(player.freq);
+
+        if let Some(conn) = connections.get_mut(&player.addr) {
+            conn.send_reliable_message(game_socket, &packet.data[..packet.size]);
+        }
+    }
+
+    fn send_reliable_to_arena(
+        &self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        arena: &str,
+        packet: &Packet,
+    ) {
+        for player in self.player_manager.players.values() {
+            if player.arena.as_deref() != Some(arena) {
+                continue;
+            }
+
+            if let Some(conn) = connections.get_mut(&player.addr) {
+                conn.send_reliable_message(game_socket, &packet.data[..packet.size]);
+            }
+        }
+    }
+
+    // Sends the arena list with the player count of each arena. The requester's current arena has its count negated
+    // so the client can highlight it.
+    fn send_arena_list(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        addr: SocketAddr,
+    ) {
+        let Some(conn) = connections.get_mut(&addr) else {
+            return;
+        };
+
+        let Some(player) = self.player_manager.get_player_by_id(conn.player_id) else {
+            return;
+        };
+
+        let current_arena = player.arena.clone();
+        let show_private = self.groups.has_capability(&player.group, "seeprivarena");
+
+        let mut arena_names: Vec<&String> = self.arena_manager.arenas.keys().collect();
+        arena_names.sort();
+
+        let mut packet = Packet::empty().concat_u8(0x2F);
+
+        for name in arena_names {
+            let is_current = current_arena.as_ref() == Some(name);
+
+            if is_private_arena(name) && !show_private && !is_current {
+                continue;
+            }
+
+            // Leave room for the reliable header.
+            if packet.size + name.len() + 3 > MAX_PACKET_SIZE - 6 {
+                println!("Arena list truncated at arena {}", name);
+                break;
+            }
+
+            let mut count = self.player_manager.arena_population(name) as i16;
+            if is_current {
+                count = -count;
+            }
+
+            packet.data[packet.size..packet.size + name.len()].copy_from_slice(name.as_bytes());
+            packet.size += name.len();
+            packet.write_u8(0);
+            packet.write_i16(count);
+        }
+
+        conn.send_reliable_message(game_socket, &packet.data[..packet.size]);
+    }
+
+    fn broadcast_player_enter(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        player_id: PlayerId,
+    ) {
+        let mut packet = Packet::empty();
+
+        let Some(join_player) = self.player_manager.get_player_by_id(player_id) else {
+            return;
+        };
+
+        let Some(arena) = join_player.arena.clone() else {
+            return;
+        };
+
+        join_player.write_enter_packet(&mut packet);
+
+        for (id, player) in self.player_manager.players.iter() {
+            if *id == player_id || player.arena.as_ref() != Some(&arena) {
+                continue;
+            }
+
+            if let Some(conn) = connections.get_mut(&player.addr) {
+                conn.send_reliable_message(game_socket, &packet.data[..packet.size]);
+            }
+        }
+    }
+
+    // Removes the player from their current arena and notifies the remaining players in it.
+    // The arena is destroyed once the last player leaves.
+    fn leave_arena(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        player_id: PlayerId,
+    ) {
+        let Some(player) = self.player_manager.get_player_by_id(player_id) else {
+            return;
+        };
+
+        let Some(arena) = player.arena.take() else {
+            return;
+        };
+
+        let score = std::mem::take(&mut player.score);
+        let name = player.name.clone();
+
+        player.ship = SHIP_SPECTATOR;
+        player.attached_to = None;
+        player.flags_carried = 0;
+        player.has_koth = false;
+        player.spectating = None;
+        player.last_position_sent.clear();
+
+        let packet = Packet::empty().concat_u8(0x04).concat_u16(player_id);
+        self.send_reliable_to_arena(game_socket, connections, &arena, &packet);
+
+        // The score table outlives the arena, so the score is kept even if this was the last player.
+        self.arena_manager.store_score(&arena, &name, score);
+
+        if self.player_manager.arena_population(&arena) == 0 {
+            self.arena_manager.remove(&arena);
+        }
+    }
+
+    fn broadcast_player_leave(
+        &mut self,
+        game_socket: &UdpSocket,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        player_id: PlayerId,
+    ) {
+        self.leave_arena(game_socket, connections, player_id);
+        self.player_manager.remove_player(player_id);
+    }
+}
+
+fn register_commands() -> CommandRegistry {
+    let mut commands = CommandRegistry::new();
+
+    command::register(&mut commands);
+    arena::commands::register(&mut commands);
+    chat::commands::register(&mut commands);
+    security::commands::register(&mut commands);
+
+    commands
+}
+
+struct Server {
+    ping_socket: UdpSocket,
+    game_socket: UdpSocket,
+
+    connections: HashMap<SocketAddr, Connection>,
+    game: Game,
+}
+
+impl Server {
+    fn new(port: u16, config: Config) -> Result<Self, std::io::Error> {
+        let game_socket = UdpSocket::bind(format!("0.0.0.0:{}", port))?;
+        let ping_socket = UdpSocket::bind(format!("0.0.0.0:{}", port + 1))?;
+
+        game_socket.set_nonblocking(true)?;
+        ping_socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            ping_socket,
+            game_socket,
+            connections: HashMap::new(),
+            game: Game::new(config),
+        })
+    }
+
+    fn remove_connection(&mut self, addr: SocketAddr) {
+        let Some(player_id) = self.connections.get(&addr).map(|conn| conn.player_id) else {
+            return;
+        };
+
+        if let Some(conn) = self.connections.remove(&addr).as_mut() {
+            conn.send_disconnect(&self.game_socket);
+        }
+
+        self.game
+            .broadcast_player_leave(&self.game_socket, &mut self.connections, player_id);
+    }
+
+    fn timeout_connection(&mut self) {
+        const TIMEOUT_TICKS: i32 = 1000;
+        let mut remove_addr = None;
+
+        let now = Tick::now();
+
+        // Times out the first connection that hasn't sent data recently.
+        for (addr, connection) in &mut self.connections {
+            let ticks_since_data = now.diff(&connection.last_packet_time);
+
+            if ticks_since_data >= TIMEOUT_TICKS {
+                println!("Timing out {:?}", addr);
+                remove_addr = Some(addr.to_owned());
+                break;
+            }
+        }
+
+        if let Some(addr) = remove_addr {
+            self.remove_connection(addr);
+        }
+    }
+
+    fn poll_game(&mut self) -> std::io::Result<()> {
+        self.timeout_connection();
+
+        let mut buf = [0; MAX_PACKET_SIZE];
+        let (size, src) = match self.game_socket.recv_from(&mut buf) {
+            Ok(r) => Ok((r.0, r.1)),
+            Err(e) => {
+                if e.kind() == std::io::ErrorKind::WouldBlock {
+                    return Ok(());
+                }
+                Err(e)
+            }
+        }?;
+
+        let buf = &buf[..size];
+
+        let connection = match self.connections.get_mut(&src) {
+            Some(connection) => connection,
+            None => {
+                let pkt_type = buf[0];
+                if pkt_type == 0x00 && size >= 8 && buf[1] == 0x01 {
+                    let key: u32 = u32::from_le_bytes(buf[2..6].try_into().unwrap()); // Key
+                    let _: u16 = u16::from_le_bytes(buf[6..8].try_into().unwrap()); // Version
+
+                    let mut response = [0; 7];
+                    response[0] = 0x00;
+                    response[1] = 0x02;
+                    response[2..6].copy_from_slice(&key.to_le_bytes()); // Send key back to disable encryption
+                    response[6] = 0x00; // No billing
+
+                    self.game_socket.send_to(&response, src)?;
+
+                    self.connections.insert(src, Connection::new(src));
+                    println!("Adding new connection");
+                }
+                return Ok(());
+            }
+        };
+
+        println!("Recv: {:?}", buf);
+
+        connection.last_packet_time = Tick::now();
+
+        let addr = connection.addr;
+
+        if !self.game.on_data(
+            &self.game_socket,
+            &mut self.connections,
+            addr,
+            Packet::new(buf),
+        ) {
+            self.remove_connection(addr);
         }
 
         Ok(())
     }
 
+    fn update(&mut self) {
+        self.game.update(&self.game_socket, &mut self.connections);
+
+        for connection in self.connections.values_mut() {
+            connection.send_outbound_chunks(&self.game_socket);
+        }
+    }
+
     fn poll_ping(&self) -> std::io::Result<()> {
         let mut buf = [0; MAX_PACKET_SIZE];
         let (size, src) = match self.ping_socket.recv_from(&mut buf) {
@@ -555,16 +2077,18 @@ impl Server {
         pong_pkt[..4].copy_from_slice(&player_count.to_le_bytes());
         pong_pkt[4..8].copy_from_slice(&timestamp.to_le_bytes());
 
-        self.ping_socket.send_to(&pong_pkt, &src)?;
+        self.ping_socket.send_to(&pong_pkt, src)?;
         Ok(())
     }
 }
 
 fn main() -> std::io::Result<()> {
-    let mut server = Server::new(5000)?;
+    let config = Config::load(CONFIG_PATH);
+    let mut server = Server::new(5000, config)?;
 
     loop {
         server.poll_ping()?;
         server.poll_game()?;
+        server.update();
     }
 }
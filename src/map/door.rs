@@ -0,0 +1,129 @@
+use crate::clock::Tick;
+
+// Random number generator used by the client for doors and prizes. Both sides must generate the same sequence from
+// the seed in the security packet.
+pub struct VieRng {
+    seed: u32,
+}
+
+impl VieRng {
+    pub fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+
+    // The client's step is the Park-Miller minimal standard generator using Schrage's method, except that it adds 0x7B
+    // to every result and wraps results of zero as well as negative ones.
+    pub fn get_next(&mut self) -> u32 {
+        let seed = self.seed as i32 as i64;
+        let high = seed / 0x1F31D;
+        let low = seed % 0x1F31D;
+
+        let mut seed = (low * 16807 - high * 0xB14 + 0x7B) as i32;
+        if seed <= 0 {
+            seed = seed.wrapping_add(0x7FFFFFFF);
+        }
+
+        self.seed = seed as u32;
+        self.seed
+    }
+}
+
+// Door state where every bit is set, so every door is closed.
+pub const DOORS_CLOSED: u8 = 0xFF;
+
+// Simulates the door cycle the same way the client does. Every door delay the doors change state based on the door
+// mode:
+// -2: Every door is completely random.
+// -1: Weighted random where the lower numbered doors are open more often. The client's weighting hasn't been ported
+//     yet, so in this mode the server's door state can differ from what players see.
+// 0-255: Fixed state where each bit is one door.
+//
+// A set bit in the door state means the door of that index is closed.
+pub struct Doors {
+    pub seed: u32,
+    // The time that the seed was set. This is sent to the client so it can catch up to the current state.
+    pub seed_time: Tick,
+    pub state: u8,
+
+    rng: VieRng,
+    last_update: Tick,
+}
+
+impl Doors {
+    pub fn new(seed: u32) -> Self {
+        let now = Tick::now();
+
+        Self {
+            seed,
+            seed_time: now,
+            state: DOORS_CLOSED,
+            rng: VieRng::new(seed),
+            last_update: now,
+        }
+    }
+
+    // Steps the doors forward for every door delay that has passed. Returns true if the state changed.
+    pub fn update(&mut self, now: Tick, door_delay: i16, door_mode: i16) -> bool {
+        let previous_state = self.state;
+
+        if door_mode >= 0 {
+            self.state = door_mode as u8;
+            self.last_update = now;
+            return self.state != previous_state;
+        }
+
+        // A delay of zero would never advance, so treat it as the smallest delay.
+        let door_delay = (door_delay as i32).max(1);
+
+        while now.diff(&self.last_update) >= door_delay {
+            self.state = self.next_state(door_mode);
+            self.last_update = Tick::new(self.last_update.value().wrapping_add(door_delay as u32));
+        }
+
+        self.state != previous_state
+    }
+
+    fn next_state(&mut self, door_mode: i16) -> u8 {
+        if door_mode == -2 {
+            return self.rng.get_next() as u8;
+        }
+
+        let mut state = 0;
+
+        for door in 0..8 {
+            // Door n is closed with a chance of (n + 1) / 9.
+            if self.rng.get_next() % 9 <= door {
+                state |= 1 << door;
+            }
+        }
+
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The client's step reduces to (seed * 16807 + 0x7B) mod (2^31 - 1) for seeds inside the generator's range.
+    #[test]
+    fn rng_matches_closed_form() {
+        let mut rng = VieRng::new(0x1234567);
+        let mut expected: u64 = 0x1234567;
+
+        for _ in 0..1000 {
+            expected = (expected * 16807 + 0x7B) % 0x7FFFFFFF;
+
+            assert_eq!(rng.get_next() as u64, expected);
+        }
+    }
+
+    #[test]
+    fn random_mode_uses_low_byte() {
+        let mut doors = Doors::new(42);
+        let mut rng = VieRng::new(42);
+
+        assert_eq!(doors.next_state(-2), rng.get_next() as u8);
+        assert_eq!(doors.next_state(-2), rng.get_next() as u8);
+    }
+}
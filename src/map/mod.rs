@@ -0,0 +1,250 @@
+use crate::map::door::DOORS_CLOSED;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use std::io::Write;
+use std::path::Path;
+
+pub mod door;
+
+pub const MAP_SIZE: usize = 1024;
+pub const MAP_DIRECTORY: &str = "maps";
+
+// The pub.lvl that comes with the client. Arenas whose map can't be loaded send this instead so clients play on their
+// own copy rather than waiting on a download that the server can't send.
+pub const STOCK_MAP_FILENAME: &str = "pub.lvl";
+pub const STOCK_MAP_CHECKSUM: u32 = 1889723958;
+pub const STOCK_MAP_FILESIZE: u32 = 58992;
+
+// The filename field in the map info packet is 16 bytes.
+pub const MAX_MAP_FILENAME_LEN: usize = 16;
+
+// Size of a tile in pixels.
+pub const TILE_SIZE: u16 = 16;
+
+pub const TILE_DOOR_FIRST: u8 = 162;
+pub const TILE_DOOR_LAST: u8 = 169;
+pub const TILE_FLAG: u8 = 170;
+pub const TILE_SAFE: u8 = 171;
+pub const TILE_GOAL: u8 = 172;
+pub const TILE_LARGE_ASTEROID: u8 = 217;
+pub const TILE_SPACE_STATION: u8 = 219;
+pub const TILE_WORMHOLE: u8 = 220;
+
+// A file that the client downloads when entering an arena, either the lvl or an lvz.
+pub struct LevelFile {
+    // Filename that is sent to the client in the map info packet.
+    pub filename: String,
+    // CRC32 of the entire file. The client compares this against its copy of the file to know if it must download it.
+    pub checksum: u32,
+    pub filesize: u32,
+    // The compressed file packet that is sent to clients that request the file. This is built once when the file is
+    // loaded.
+    pub compressed: Vec<u8>,
+}
+
+impl LevelFile {
+    // Reads a file from the map directory. The file's contents are returned along with it so they can be parsed.
+    pub fn load(filename: &str) -> Option<(Self, Vec<u8>)> {
+        let path = Path::new(MAP_DIRECTORY).join(filename);
+
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                println!("Failed to read level file {}: {}", path.display(), e);
+                return None;
+            }
+        };
+
+        let filename = path.file_name()?.to_string_lossy().into_owned();
+
+        if filename.len() > MAX_MAP_FILENAME_LEN {
+            println!(
+                "Level filename {} is longer than {} bytes.",
+                filename, MAX_MAP_FILENAME_LEN
+            );
+            return None;
+        }
+
+        let mut crc = flate2::Crc::new();
+        crc.update(&data);
+
+        let compressed = match compress_file(&filename, &data) {
+            Ok(compressed) => compressed,
+            Err(e) => {
+                println!("Failed to compress level file {}: {}", filename, e);
+                return None;
+            }
+        };
+
+        let file = Self {
+            filename,
+            checksum: crc.sum(),
+            filesize: data.len() as u32,
+            compressed,
+        };
+
+        Some((file, data))
+    }
+}
+
+pub struct Map {
+    pub file: LevelFile,
+    // Current state of the doors from the arena's door cycle. A set bit means that door is closed.
+    pub door_state: u8,
+
+    tiles: Vec<u8>,
+}
+
+impl Map {
+    // Loads a map from the map directory.
+    pub fn load(filename: &str) -> Option<Self> {
+        let (file, data) = LevelFile::load(filename)?;
+        let tiles = parse_tiles(&file.filename, &data)?;
+
+        Some(Self {
+            file,
+            door_state: DOORS_CLOSED,
+            tiles,
+        })
+    }
+
+    // Gets the tile id at the tile coordinates. Everything outside of the map is treated as empty.
+    pub fn get_tile(&self, x: u16, y: u16) -> u8 {
+        let (x, y) = (x as usize, y as usize);
+
+        if x >= MAP_SIZE || y >= MAP_SIZE {
+            return 0;
+        }
+
+        self.tiles[y * MAP_SIZE + x]
+    }
+
+    // Checks if ships collide with the tile. Doors are solid while they are closed.
+    pub fn is_solid(&self, x: u16, y: u16) -> bool {
+        // The edge of the map acts as a wall.
+        if x as usize >= MAP_SIZE || y as usize >= MAP_SIZE {
+            return true;
+        }
+
+        let tile = self.get_tile(x, y);
+
+        if is_door_tile(tile) {
+            return self.door_state & (1 << (tile - TILE_DOOR_FIRST)) != 0;
+        }
+
+        is_solid_tile(tile)
+    }
+
+    pub fn is_safe(&self, x: u16, y: u16) -> bool {
+        self.get_tile(x, y) == TILE_SAFE
+    }
+
+    pub fn is_door(&self, x: u16, y: u16) -> bool {
+        is_door_tile(self.get_tile(x, y))
+    }
+
+    pub fn is_goal(&self, x: u16, y: u16) -> bool {
+        self.get_tile(x, y) == TILE_GOAL
+    }
+
+    pub fn is_flag(&self, x: u16, y: u16) -> bool {
+        self.get_tile(x, y) == TILE_FLAG
+    }
+
+    pub fn is_wormhole(&self, x: u16, y: u16) -> bool {
+        self.get_tile(x, y) == TILE_WORMHOLE
+    }
+
+    // Checks if a pixel position is inside of a safe zone.
+    pub fn is_safe_position(&self, x: u16, y: u16) -> bool {
+        self.is_safe(x / TILE_SIZE, y / TILE_SIZE)
+    }
+
+    // Checks if a ship with the radius in pixels can be at the pixel position without overlapping a solid tile.
+    pub fn can_occupy(&self, x: u16, y: u16, radius: u16) -> bool {
+        let start_x = x.saturating_sub(radius) / TILE_SIZE;
+        let start_y = y.saturating_sub(radius) / TILE_SIZE;
+        // Subtract one so a ship that is exactly touching the next tile isn't counted as overlapping it.
+        let end_x = x.saturating_add(radius).saturating_sub(1) / TILE_SIZE;
+        let end_y = y.saturating_add(radius).saturating_sub(1) / TILE_SIZE;
+
+        for tile_y in start_y..=end_y {
+            for tile_x in start_x..=end_x {
+                if self.is_solid(tile_x, tile_y) {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+}
+
+pub fn is_door_tile(tile: u8) -> bool {
+    (TILE_DOOR_FIRST..=TILE_DOOR_LAST).contains(&tile)
+}
+
+// Normal tiles, doors, asteroids and the space station block ships. Safe zones, flags, goals, fly over and fly under
+// tiles and wormholes do not.
+pub fn is_solid_tile(tile: u8) -> bool {
+    matches!(tile, 1..=TILE_DOOR_LAST | 191..=TILE_SPACE_STATION)
+}
+
+// Large tiles are stored once in the lvl file at their top left corner, but they take up multiple tiles.
+fn tile_dimensions(tile: u8) -> usize {
+    match tile {
+        TILE_LARGE_ASTEROID => 2,
+        TILE_SPACE_STATION => 6,
+        TILE_WORMHOLE => 5,
+        _ => 1,
+    }
+}
+
+fn parse_tiles(filename: &str, data: &[u8]) -> Option<Vec<u8>> {
+    let mut tiles = vec![0; MAP_SIZE * MAP_SIZE];
+    let mut tile_data = data;
+
+    // The lvl file can start with a bitmap of the tileset. The bitmap header contains its total size, so skip
+    // over it to get to the tile list.
+    if tile_data.len() >= 6 && &tile_data[..2] == b"BM" {
+        let bitmap_size = u32::from_le_bytes(tile_data[2..6].try_into().unwrap()) as usize;
+
+        if bitmap_size > tile_data.len() {
+            println!("Map {} has an invalid tileset size.", filename);
+            return None;
+        }
+
+        tile_data = &tile_data[bitmap_size..];
+    }
+
+    for entry in tile_data.chunks_exact(4) {
+        let entry = u32::from_le_bytes(entry.try_into().unwrap());
+
+        let x = (entry & 0xFFF) as usize;
+        let y = ((entry >> 12) & 0xFFF) as usize;
+        let tile = (entry >> 24) as u8;
+
+        let dimensions = tile_dimensions(tile);
+
+        for y in y..(y + dimensions).min(MAP_SIZE) {
+            for x in x..(x + dimensions).min(MAP_SIZE) {
+                tiles[y * MAP_SIZE + x] = tile;
+            }
+        }
+    }
+
+    Some(tiles)
+}
+
+// Builds the compressed file packet: the 0x2A type, the 16 byte filename, then the zlib compressed file.
+pub fn compress_file(filename: &str, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut header = [0; MAX_MAP_FILENAME_LEN + 1];
+    let name_len = filename.len().min(MAX_MAP_FILENAME_LEN);
+
+    header[0] = 0x2A;
+    header[1..name_len + 1].copy_from_slice(&filename.as_bytes()[..name_len]);
+
+    let mut encoder = ZlibEncoder::new(header.to_vec(), Compression::best());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
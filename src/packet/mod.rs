@@ -1,10 +1,18 @@
 use crate::clock::Tick;
 use std::fmt;
 
+pub mod position;
 pub mod sequencer;
 
 pub const MAX_PACKET_SIZE: usize = 520;
 
+// Reads a fixed size string field that is padded with null bytes.
+pub fn read_fixed_string(data: &[u8]) -> String {
+    let end = data.iter().position(|c| *c == 0).unwrap_or(data.len());
+
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
 #[derive(Copy, Clone)]
 pub struct Packet {
     pub data: [u8; MAX_PACKET_SIZE],
@@ -0,0 +1,167 @@
+use crate::packet::Packet;
+use crate::player::PlayerId;
+
+pub const POSITION_PACKET_SIZE: usize = 22;
+pub const EXTRA_POSITION_DATA_SIZE: usize = 10;
+
+// Extra data that the client appends to the position packet when the arena enables it.
+#[derive(Copy, Clone, Debug)]
+pub struct ExtraPositionData {
+    pub energy: u16,
+    pub s2c_latency: u16,
+    pub timers: u16,
+    pub items: u32,
+}
+
+impl ExtraPositionData {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < EXTRA_POSITION_DATA_SIZE {
+            return None;
+        }
+
+        Some(Self {
+            energy: u16::from_le_bytes(buf[0..2].try_into().unwrap()),
+            s2c_latency: u16::from_le_bytes(buf[2..4].try_into().unwrap()),
+            timers: u16::from_le_bytes(buf[4..6].try_into().unwrap()),
+            items: u32::from_le_bytes(buf[6..10].try_into().unwrap()),
+        })
+    }
+
+    pub fn write(&self, packet: &mut Packet) {
+        packet.write_u16(self.energy);
+        packet.write_u16(self.s2c_latency);
+        packet.write_u16(self.timers);
+        packet.write_u32(self.items);
+    }
+}
+
+// C2S position packet
+#[derive(Copy, Clone, Debug)]
+pub struct PositionPacket {
+    pub rotation: u8,
+    pub timestamp: u32,
+    pub x_velocity: i16,
+    pub y: u16,
+    pub checksum: u8,
+    pub status: u8,
+    pub x: u16,
+    pub y_velocity: i16,
+    pub bounty: u16,
+    pub energy: i16,
+    pub weapon: u16,
+
+    pub extra: Option<ExtraPositionData>,
+}
+
+impl PositionPacket {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < POSITION_PACKET_SIZE {
+            return None;
+        }
+
+        Some(Self {
+            rotation: buf[1],
+            timestamp: u32::from_le_bytes(buf[2..6].try_into().unwrap()),
+            x_velocity: i16::from_le_bytes(buf[6..8].try_into().unwrap()),
+            y: u16::from_le_bytes(buf[8..10].try_into().unwrap()),
+            checksum: buf[10],
+            status: buf[11],
+            x: u16::from_le_bytes(buf[12..14].try_into().unwrap()),
+            y_velocity: i16::from_le_bytes(buf[14..16].try_into().unwrap()),
+            bounty: u16::from_le_bytes(buf[16..18].try_into().unwrap()),
+            energy: i16::from_le_bytes(buf[18..20].try_into().unwrap()),
+            weapon: u16::from_le_bytes(buf[20..22].try_into().unwrap()),
+            extra: ExtraPositionData::parse(&buf[POSITION_PACKET_SIZE..]),
+        })
+    }
+
+    // Every byte of the packet, including the checksum, xors to zero.
+    pub fn verify_checksum(buf: &[u8]) -> bool {
+        if buf.len() < POSITION_PACKET_SIZE {
+            return false;
+        }
+
+        buf[..POSITION_PACKET_SIZE]
+            .iter()
+            .fold(0, |checksum, value| checksum ^ value)
+            == 0
+    }
+
+    pub fn speed(&self) -> u32 {
+        let x = self.x_velocity as f64;
+        let y = self.y_velocity as f64;
+
+        (x * x + y * y).sqrt() as u32
+    }
+
+    // The small position packet can only be used when there's no weapon and the player id and bounty fit in a byte.
+    pub fn fits_small_packet(&self, pid: PlayerId) -> bool {
+        self.weapon == 0 && pid <= 0xFF && self.bounty <= 0xFF
+    }
+
+    // Builds the S2C small position packet 0x28. The extra data is only included for receivers that may see it.
+    pub fn build_small(
+        &self,
+        pid: PlayerId,
+        timestamp: u16,
+        latency: u8,
+        include_extra: bool,
+    ) -> Packet {
+        let mut packet = Packet::empty()
+            .concat_u8(0x28)
+            .concat_u8(self.rotation)
+            .concat_u16(timestamp)
+            .concat_u16(self.x)
+            .concat_u8(latency)
+            .concat_u8(self.bounty as u8)
+            .concat_u8(pid as u8)
+            .concat_u8(self.status)
+            .concat_i16(self.y_velocity)
+            .concat_u16(self.y)
+            .concat_i16(self.x_velocity);
+
+        if let Some(extra) = self.extra.filter(|_| include_extra) {
+            extra.write(&mut packet);
+        }
+
+        packet
+    }
+
+    // Builds the S2C weapon packet 0x05. This is used for any position with a weapon or values that are too large for
+    // the small packet.
+    pub fn build_weapon(
+        &self,
+        pid: PlayerId,
+        timestamp: u16,
+        latency: u8,
+        include_extra: bool,
+    ) -> Packet {
+        const CHECKSUM_INDEX: usize = 12;
+        const WEAPON_PACKET_SIZE: usize = 21;
+
+        let mut packet = Packet::empty()
+            .concat_u8(0x05)
+            .concat_u8(self.rotation)
+            .concat_u16(timestamp)
+            .concat_u16(self.x)
+            .concat_i16(self.y_velocity)
+            .concat_u16(pid)
+            .concat_i16(self.x_velocity)
+            .concat_u8(0) // Checksum
+            .concat_u8(self.status)
+            .concat_u8(latency)
+            .concat_u16(self.y)
+            .concat_u16(self.bounty)
+            .concat_u16(self.weapon);
+
+        packet.data[CHECKSUM_INDEX] = packet.data[..WEAPON_PACKET_SIZE]
+            .iter()
+            .fold(0, |checksum, value| checksum ^ value);
+
+        if let Some(extra) = self.extra.filter(|_| include_extra) {
+            extra.write(&mut packet);
+        }
+
+        packet
+    }
+}
@@ -1,5 +1,6 @@
 use crate::clock::Tick;
 use crate::packet::{MAX_PACKET_SIZE, Packet};
+use std::collections::VecDeque;
 
 pub struct ReliableMessage {
     pub id: u32,
@@ -14,7 +15,7 @@ impl ReliableMessage {
 
         // Is rust a real language? How the fuck do I initialize the struct without this unnecessary variable?
         let mut new_message: [u8; MAX_PACKET_SIZE] = [0; MAX_PACKET_SIZE];
-        new_message[..len].copy_from_slice(&message);
+        new_message[..len].copy_from_slice(message);
 
         Self {
             id,
@@ -57,49 +58,60 @@ pub struct PacketSequencer {
     pub reliable_sent: Vec<ReliableMessage>,
     pub reliable_queue: Vec<ReliableMessage>,
 
-    pub outbound_chunked: Option<OutboundChunkedPacket>,
+    // Huge messages waiting to be sent. Only the front one is sent at a time.
+    pub outbound_chunked: VecDeque<OutboundChunkedPacket>,
 }
 
 impl Iterator for PacketSequencer {
     type Item = Packet;
 
-    // This will produce raw packets that should be sent.
+    // This will produce the next huge chunk message of the current outbound chunked packet.
+    // The message must be sent as a reliable message with the current reliable id, which is tracked here so the
+    // transfer can wait on its ack.
     fn next(&mut self) -> Option<Self::Item> {
         // TODO: Go through reliable sent and determine if we should resend.
 
-        if let Some(outbound_chunked) = &mut self.outbound_chunked {
-            if outbound_chunked.outbound_ids.len() < outbound_chunked.max_outbound {
-                outbound_chunked.max_outbound += 1;
+        let outbound_chunked = self.outbound_chunked.front_mut()?;
 
-                let header_size = 6;
-
-                let mut size = outbound_chunked.get_remaining();
-                if size > MAX_PACKET_SIZE - header_size {
-                    size = MAX_PACKET_SIZE - header_size;
-                }
+        if outbound_chunked.outbound_ids.len() >= outbound_chunked.max_outbound {
+            return None;
+        }
 
-                let mut data = [0; MAX_PACKET_SIZE];
+        // Huge chunk header and reliable header
+        let header_size = 6 + 6;
 
-                data[0] = 0x00;
-                data[1] = 0x0A;
-                data[2..6].copy_from_slice(&(size as u32).to_le_bytes());
-                data[6..size + 6].copy_from_slice(
-                    &outbound_chunked.data[outbound_chunked.index..outbound_chunked.index + size],
-                );
+        let mut size = outbound_chunked.get_remaining();
+        if size > MAX_PACKET_SIZE - header_size {
+            size = MAX_PACKET_SIZE - header_size;
+        }
 
-                outbound_chunked.index += size;
+        let total_size = outbound_chunked.data.len() as u32;
+        let mut packet = Packet::empty()
+            .concat_u8(0x00)
+            .concat_u8(0x0A)
+            .concat_u32(total_size);
 
-                if outbound_chunked.get_remaining() == 0 {
-                    self.outbound_chunked = None;
-                }
+        packet.data[6..size + 6].copy_from_slice(
+            &outbound_chunked.data[outbound_chunked.index..outbound_chunked.index + size],
+        );
+        packet.size += size;
 
-                return Some(Packet::new(&data[..size]));
-            }
+        outbound_chunked.index += size;
+        outbound_chunked
+            .outbound_ids
+            .push(self.next_reliable_gen_id);
 
-            self.outbound_chunked = None;
+        if outbound_chunked.get_remaining() == 0 {
+            self.outbound_chunked.pop_front();
         }
 
-        None
+        Some(packet)
+    }
+}
+
+impl Default for PacketSequencer {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -111,7 +123,7 @@ impl PacketSequencer {
             reliable_sent: Vec::new(),
             reliable_queue: Vec::new(),
 
-            outbound_chunked: None,
+            outbound_chunked: VecDeque::new(),
         }
     }
 
@@ -133,14 +145,13 @@ impl PacketSequencer {
         if let Some(index) = self.reliable_sent.iter().position(|msg| msg.id == id) {
             self.reliable_sent.swap_remove(index);
 
-            if let Some(outbound_chunked) = &mut self.outbound_chunked {
-                if let Some(index) = outbound_chunked
+            if let Some(outbound_chunked) = self.outbound_chunked.front_mut()
+                && let Some(index) = outbound_chunked
                     .outbound_ids
                     .iter()
                     .position(|outbound_id| *outbound_id == id)
-                {
-                    outbound_chunked.outbound_ids.swap_remove(index);
-                }
+            {
+                outbound_chunked.outbound_ids.swap_remove(index);
             }
         }
     }
@@ -1,15 +1,85 @@
-use std::collections::HashMap;
+use crate::clock::Tick;
+use crate::login::LoginRequest;
+use crate::packet::Packet;
+use crate::security::group::DEFAULT_GROUP;
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
 
 pub type PlayerId = u16;
 pub const INVALID_PLAYER_ID: PlayerId = 65535;
 
+// Ships are numbered 0 to 7 with 8 being spectator mode.
+pub const SHIP_SPECTATOR: u8 = 8;
+
+pub const ENTER_PACKET_SIZE: usize = 64;
+// Names and squads are sent in 20 byte fields in the enter packet.
+pub const MAX_NAME_FIELD_LEN: usize = 20;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Score {
+    pub kill_points: u32,
+    pub flag_points: u32,
+    pub wins: u16,
+    pub losses: u16,
+}
+
+// Who receives a prize that the server grants.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PrizeTarget {
+    Player(PlayerId),
+    // Every player in a ship on the freq in the granting player's arena.
+    Freq(u16),
+}
+
 #[derive(Debug)]
 pub struct Player {
     pub id: PlayerId,
     pub name: String,
+    pub squad: String,
 
     pub addr: SocketAddr,
+    // Staff group that decides which capabilities the player has.
+    pub group: String,
+    // The login packet the player connected with, kept for bans and auditing. The password is cleared.
+    pub login: Option<LoginRequest>,
+
+    // Name of the arena the player is in. This is None until the player sends an arena login.
+    pub arena: Option<String>,
+    pub ship: u8,
+    pub freq: u16,
+    pub accept_audio: bool,
+
+    pub score: Score,
+    // The player this player is attached to as a turret.
+    pub attached_to: Option<PlayerId>,
+    pub flags_carried: u16,
+    pub has_koth: bool,
+    // Number of green prizes the player has picked up this session.
+    pub greens_picked_up: u32,
+    // Chat channels the player is subscribed to. The index in this list is the channel number the player uses.
+    pub chat_channels: Vec<String>,
+    // Times of the player's recent messages for flood protection.
+    pub chat_times: VecDeque<Tick>,
+    // The player can't chat until this time.
+    pub muted_until: Option<Tick>,
+    // Failed *login attempts in a row and the time the player can try again after too many of them.
+    pub group_login_failures: u32,
+    pub group_login_locked_until: Option<Tick>,
+
+    // Last ship state that the player sent in a position packet. Positions are in pixels.
+    pub x: u16,
+    pub y: u16,
+    pub x_velocity: i16,
+    pub y_velocity: i16,
+    pub rotation: u8,
+    pub status: u8,
+    pub bounty: u16,
+    pub energy: i16,
+
+    // The player being spectated. Distance for position culling is measured from this player instead.
+    pub spectating: Option<PlayerId>,
+    // The last time a position packet from each player in the arena was sent to this player.
+    pub last_position_sent: HashMap<PlayerId, Tick>,
 }
 
 impl Player {
@@ -17,11 +87,81 @@ impl Player {
         Self {
             id,
             name: String::new(),
+            squad: String::new(),
             addr,
+            group: DEFAULT_GROUP.to_owned(),
+            login: None,
+            arena: None,
+            ship: SHIP_SPECTATOR,
+            freq: 0,
+            accept_audio: false,
+            score: Score::default(),
+            attached_to: None,
+            flags_carried: 0,
+            has_koth: false,
+            greens_picked_up: 0,
+            chat_channels: vec![],
+            chat_times: VecDeque::new(),
+            muted_until: None,
+            group_login_failures: 0,
+            group_login_locked_until: None,
+            x: 0,
+            y: 0,
+            x_velocity: 0,
+            y_velocity: 0,
+            rotation: 0,
+            status: 0,
+            bounty: 0,
+            energy: 0,
+            spectating: None,
+            last_position_sent: HashMap::new(),
         }
     }
 }
 
+impl Player {
+    // Builds the S2C score update packet 0x09.
+    pub fn score_packet(&self) -> Packet {
+        Packet::empty()
+            .concat_u8(0x09)
+            .concat_u16(self.id)
+            .concat_u32(self.score.kill_points)
+            .concat_u32(self.score.flag_points)
+            .concat_u16(self.score.wins)
+            .concat_u16(self.score.losses)
+    }
+
+    // Appends the S2C player entering packet to the packet. Multiple enter packets can be sent in one message.
+    pub fn write_enter_packet(&self, packet: &mut Packet) {
+        let start = packet.size;
+
+        packet.write_u8(0x03);
+        packet.write_u8(self.ship);
+        packet.write_u8(self.accept_audio as u8);
+        write_name_field(packet, &self.name);
+        write_name_field(packet, &self.squad);
+        packet.write_u32(self.score.kill_points);
+        packet.write_u32(self.score.flag_points);
+        packet.write_u16(self.id);
+        packet.write_u16(self.freq);
+        packet.write_u16(self.score.wins);
+        packet.write_u16(self.score.losses);
+        packet.write_u16(self.attached_to.unwrap_or(INVALID_PLAYER_ID));
+        packet.write_u16(self.flags_carried);
+        packet.write_u8(self.has_koth as u8);
+
+        debug_assert_eq!(packet.size - start, ENTER_PACKET_SIZE);
+    }
+}
+
+fn write_name_field(packet: &mut Packet, name: &str) {
+    let len = name.len().min(MAX_NAME_FIELD_LEN);
+
+    packet.data[packet.size..packet.size + MAX_NAME_FIELD_LEN].fill(0);
+    packet.data[packet.size..packet.size + len].copy_from_slice(&name.as_bytes()[..len]);
+    packet.size += MAX_NAME_FIELD_LEN;
+}
+
 pub struct PlayerManager {
     pub players: HashMap<PlayerId, Player>,
     pidset: PidSet,
@@ -55,8 +195,41 @@ impl PlayerManager {
         None
     }
 
+    // Names are unique ignoring case.
+    pub fn get_player_by_name(&self, name: &str) -> Option<&Player> {
+        self.players
+            .values()
+            .find(|player| player.name.eq_ignore_ascii_case(name))
+    }
+
     pub fn remove_player(&mut self, pid: PlayerId) {
         self.players.remove(&pid);
+        self.pidset.clear(pid);
+    }
+
+    // Number of players in ships on the freq. Spectators don't count towards the freq limits.
+    pub fn freq_population(&self, arena: &str, freq: u16) -> usize {
+        self.players
+            .values()
+            .filter(|player| {
+                player.arena.as_deref() == Some(arena)
+                    && player.freq == freq
+                    && player.ship != SHIP_SPECTATOR
+            })
+            .count()
+    }
+
+    pub fn arena_population(&self, arena: &str) -> usize {
+        self.players
+            .values()
+            .filter(|player| player.arena.as_deref() == Some(arena))
+            .count()
+    }
+}
+
+impl Default for PlayerManager {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -80,6 +253,17 @@ impl PidSet {
         self.bits[array_index] |= 1 << bit_index;
     }
 
+    fn clear(&mut self, id: u16) {
+        if id >= 1024 {
+            return;
+        }
+
+        let array_index = (id / 64) as usize;
+        let bit_index = (id % 64) as usize;
+
+        self.bits[array_index] &= !(1 << bit_index);
+    }
+
     fn test(&mut self, id: u16) -> bool {
         if id >= 1024 {
             return true;
@@ -88,13 +272,13 @@ impl PidSet {
         let array_index = (id / 64) as usize;
         let bit_index = (id % 64) as usize;
 
-        return self.bits[array_index] & (1 << bit_index) != 0;
+        self.bits[array_index] & (1 << bit_index) != 0
     }
 
     fn get_and_set_free_pid(&mut self) -> Option<u16> {
         for i in 0..1024 {
-            if !self.test(i as u16) {
-                self.set(i as u16);
+            if !self.test(i) {
+                self.set(i);
                 return Some(i);
             }
         }
@@ -0,0 +1,91 @@
+use crate::clock::{TICKS_PER_SECOND, Tick};
+use crate::command::{Command, CommandContext, CommandRegistry, TargetKind};
+use crate::security::group::{GROUP_LOGIN_LOCKOUT_SECONDS, MAX_GROUP_LOGIN_FAILURES};
+use crate::{Connection, Game};
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+
+// Commands for staff groups.
+pub fn register(commands: &mut CommandRegistry) {
+    commands.register(Command {
+        name: "login",
+        usage: "<group> <password>",
+        help: "Logs in to a staff group with the group's password.",
+        capability: None,
+        staff: true,
+        targets: &[TargetKind::Arena],
+        handler: command_login,
+    });
+}
+
+// *login <group> <password> moves the player into the group if the password matches the group's password.
+fn command_login(
+    game: &mut Game,
+    game_socket: &UdpSocket,
+    connections: &mut HashMap<SocketAddr, Connection>,
+    context: &CommandContext,
+) {
+    let pid = context.sender;
+
+    let (Some(group), Some(password)) = (context.arg(0), context.arg(1)) else {
+        game.send_arena_message(
+            game_socket,
+            connections,
+            pid,
+            "Usage: *login <group> <password>",
+        );
+        return;
+    };
+
+    let now = Tick::now();
+
+    let Some(player) = game.player_manager.players.get_mut(&pid) else {
+        return;
+    };
+
+    if player
+        .group_login_locked_until
+        .is_some_and(|until| until.gt(&now))
+    {
+        println!(
+            "{} tried to log in to group {} while locked out",
+            player.name, group
+        );
+        let message = "Too many failed logins. Try again later.";
+        game.send_arena_message(game_socket, connections, pid, message);
+        return;
+    }
+
+    if !game.groups.check_password(group, password) {
+        player.group_login_failures += 1;
+
+        println!(
+            "{} failed to log in to group {} ({} in a row)",
+            player.name, group, player.group_login_failures
+        );
+
+        if player.group_login_failures < MAX_GROUP_LOGIN_FAILURES {
+            game.send_arena_message(game_socket, connections, pid, "Login failed.");
+            return;
+        }
+
+        let lockout = (GROUP_LOGIN_LOCKOUT_SECONDS * TICKS_PER_SECOND) as u32;
+
+        player.group_login_failures = 0;
+        player.group_login_locked_until = Some(Tick::new(now.value().wrapping_add(lockout)));
+
+        let message = format!(
+            "Too many failed logins. Try again in {} seconds.",
+            GROUP_LOGIN_LOCKOUT_SECONDS
+        );
+        game.send_arena_message(game_socket, connections, pid, &message);
+        return;
+    }
+
+    println!("{} logged in to group {}", player.name, group);
+    player.group_login_failures = 0;
+    player.group = group.to_lowercase();
+
+    let message = format!("Logged in to group {}.", player.group);
+    game.send_arena_message(game_socket, connections, pid, &message);
+}
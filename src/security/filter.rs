@@ -0,0 +1,135 @@
+use crate::config::Config;
+use crate::player::MAX_NAME_FIELD_LEN;
+
+// Punctuation that names can contain along with letters, numbers and spaces.
+pub const DEFAULT_NAME_CHARACTERS: &str = "-_.'[]()!?~^=+@";
+
+// Reasons a name is refused at login.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NameViolation {
+    Empty,
+    TooLong,
+    // Names must start with a letter or number and only contain the allowed punctuation.
+    InvalidCharacter,
+    Reserved,
+    Offensive,
+}
+
+pub struct FilterConfig {
+    pub max_name_len: usize,
+    name_characters: String,
+    // Names that nobody can log in with, stored in lowercase.
+    reserved_names: Vec<String>,
+    // Words that are refused in names and masked in public chat, stored in lowercase.
+    words: Vec<String>,
+    pub filter_public_chat: bool,
+}
+
+impl FilterConfig {
+    pub fn load(config: &Config) -> Self {
+        let max_name_len = config.get_int("Filter", "MaxNameLength", MAX_NAME_FIELD_LEN as i32);
+
+        Self {
+            max_name_len: (max_name_len.max(1) as usize).min(MAX_NAME_FIELD_LEN),
+            name_characters: config
+                .get_str("Filter", "NameCharacters")
+                .unwrap_or(DEFAULT_NAME_CHARACTERS)
+                .to_owned(),
+            reserved_names: lowercase_list(config.get_list("Filter", "ReservedNames")),
+            words: lowercase_list(config.get_list("Filter", "Words")),
+            filter_public_chat: config.get_int("Filter", "FilterPublicChat", 1) != 0,
+        }
+    }
+
+    pub fn check_name(&self, name: &str) -> Option<NameViolation> {
+        let Some(first) = name.chars().next() else {
+            return Some(NameViolation::Empty);
+        };
+
+        if name.len() > self.max_name_len {
+            return Some(NameViolation::TooLong);
+        }
+
+        let valid_characters = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == ' ' || self.name_characters.contains(c));
+
+        if !first.is_ascii_alphanumeric() || !valid_characters || name.ends_with(' ') {
+            return Some(NameViolation::InvalidCharacter);
+        }
+
+        let lowercase = name.to_ascii_lowercase();
+
+        if self.reserved_names.contains(&lowercase) {
+            return Some(NameViolation::Reserved);
+        }
+
+        if self
+            .words
+            .iter()
+            .any(|word| lowercase.contains(word.as_str()))
+        {
+            return Some(NameViolation::Offensive);
+        }
+
+        None
+    }
+
+    // Replaces every filtered word in the text with asterisks. Words that overlap each other are all masked.
+    pub fn mask_words(&self, text: &str) -> String {
+        let lowercase = text.to_ascii_lowercase();
+        // Whether each byte of the text is part of a filtered word.
+        let mut masked = vec![false; text.len()];
+
+        for word in &self.words {
+            let mut start = 0;
+
+            while let Some(position) = lowercase[start..].find(word.as_str()) {
+                let begin = start + position;
+
+                masked[begin..begin + word.len()].fill(true);
+
+                // Continue from the next character so matches that overlap this one are found too.
+                start = begin + lowercase[begin..].chars().next().map_or(1, char::len_utf8);
+            }
+        }
+
+        text.char_indices()
+            .map(|(index, c)| match masked[index] {
+                true => '*',
+                false => c,
+            })
+            .collect()
+    }
+}
+
+fn lowercase_list(list: Vec<String>) -> Vec<String> {
+    list.iter()
+        .map(|entry| entry.to_ascii_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(words: &[&str]) -> FilterConfig {
+        FilterConfig {
+            max_name_len: MAX_NAME_FIELD_LEN,
+            name_characters: DEFAULT_NAME_CHARACTERS.to_owned(),
+            reserved_names: vec![],
+            words: words.iter().map(|word| word.to_string()).collect(),
+            filter_public_chat: true,
+        }
+    }
+
+    #[test]
+    fn masks_overlapping_words() {
+        let filter = filter(&["abc", "bcde", "zz"]);
+
+        assert_eq!(filter.mask_words("xABCDEx"), "x*****x");
+        assert_eq!(filter.mask_words("abcabc"), "******");
+        assert_eq!(filter.mask_words("zzz and z"), "*** and z");
+    }
+}
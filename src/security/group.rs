@@ -0,0 +1,121 @@
+use crate::config::Config;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+pub const DEFAULT_GROUP: &str = "default";
+
+// Players that fail *login this many times in a row can't try again until the lockout ends.
+pub const MAX_GROUP_LOGIN_FAILURES: u32 = 3;
+pub const GROUP_LOGIN_LOCKOUT_SECONDS: i32 = 60;
+
+// Each group can do everything the groups before it can. A [Group:name] section with a Capabilities list replaces
+// everything that group would have, and the groups after it inherit the replaced list.
+const GROUP_NAMES: [&str; 4] = [DEFAULT_GROUP, "mod", "smod", "sysop"];
+
+const MOD_CAPABILITIES: &[&str] = &[
+    "seemodchat",
+    "seeprivarena",
+    "seeepd",
+    "bypasschatlimits",
+    "cmd_setship",
+    "cmd_setfreq",
+    "cmd_shutup",
+];
+const SMOD_CAPABILITIES: &[&str] = &["cmd_prize", "cmd_publicchat"];
+const SYSOP_CAPABILITIES: &[&str] = &[];
+
+pub struct Group {
+    pub name: String,
+    capabilities: Vec<String>,
+    // Password for elevating into the group with *login. Groups without one can only be assigned in [Staff].
+    password: Option<String>,
+}
+
+pub struct Groups {
+    groups: Vec<Group>,
+}
+
+impl Groups {
+    pub fn load(config: &Config) -> Self {
+        let mut groups = vec![];
+        let mut inherited: Vec<String> = vec![];
+
+        for name in GROUP_NAMES {
+            let section = format!("Group:{}", name);
+
+            let configured = config.get_list(&section, "Capabilities");
+
+            match configured.is_empty() {
+                true => inherited.extend(
+                    builtin_capabilities(name)
+                        .iter()
+                        .map(|capability| capability.to_string()),
+                ),
+                false => inherited = configured.iter().map(|c| c.to_lowercase()).collect(),
+            }
+
+            // The groups after this one start from what this group ended up with.
+            let capabilities = inherited.clone();
+
+            groups.push(Group {
+                name: name.to_owned(),
+                capabilities,
+                password: config
+                    .get_str(&section, "Password")
+                    .filter(|password| !password.is_empty())
+                    .map(|password| password.to_owned()),
+            });
+        }
+
+        Self { groups }
+    }
+
+    // Gets the group of the player from the [Staff] section, which is keyed by player name.
+    pub fn staff_group(&self, config: &Config, name: &str) -> String {
+        match config.get_str("Staff", name) {
+            Some(group) if self.get(group).is_some() => group.to_lowercase(),
+            Some(group) => {
+                println!("Unknown group {} for staff member {}", group, name);
+                DEFAULT_GROUP.to_owned()
+            }
+            None => DEFAULT_GROUP.to_owned(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Group> {
+        self.groups
+            .iter()
+            .find(|group| group.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn has_capability(&self, group: &str, capability: &str) -> bool {
+        self.get(group).is_some_and(|group| {
+            group
+                .capabilities
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(capability))
+        })
+    }
+
+    // The passwords are hashed before comparing them in constant time, so neither the time taken nor the length of
+    // the password tells anything about how close the guess was.
+    pub fn check_password(&self, group: &str, password: &str) -> bool {
+        self.get(group)
+            .and_then(|group| group.password.as_deref())
+            .is_some_and(|expected| {
+                let expected = Sha256::digest(expected.as_bytes());
+                let password = Sha256::digest(password.as_bytes());
+
+                expected.ct_eq(&password).into()
+            })
+    }
+}
+
+fn builtin_capabilities(group: &str) -> &'static [&'static str] {
+    match group {
+        "mod" => MOD_CAPABILITIES,
+        "smod" => SMOD_CAPABILITIES,
+        "sysop" => SYSOP_CAPABILITIES,
+        _ => &[],
+    }
+}
@@ -0,0 +1,69 @@
+use crate::config::Config;
+
+pub mod commands;
+pub mod filter;
+pub mod group;
+
+pub const DEFAULT_MAX_TIMESTAMP_DRIFT: i32 = 500;
+pub const DEFAULT_SPEED_TOLERANCE: i32 = 125;
+
+// What to do with a player that sends a position packet that fails validation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ViolationPolicy {
+    // Ignore the packet.
+    Drop,
+    // Ignore the packet and move the player to spectator mode.
+    Spec,
+    // Ignore the packet and disconnect the player.
+    Kick,
+}
+
+impl ViolationPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "drop" => Some(ViolationPolicy::Drop),
+            "spec" => Some(ViolationPolicy::Spec),
+            "kick" => Some(ViolationPolicy::Kick),
+            _ => None,
+        }
+    }
+}
+
+pub struct SecurityConfig {
+    pub position_policy: ViolationPolicy,
+    // How far in ticks a position timestamp can be from the server's time once converted with the client's sync.
+    pub max_timestamp_drift: i32,
+    // Percent of the fastest speed a ship can reach that is accepted before it's counted as a violation.
+    pub speed_tolerance: i32,
+}
+
+impl SecurityConfig {
+    pub fn load(config: &Config) -> Self {
+        let position_policy = match config.get_str("Security", "PositionViolation") {
+            Some(value) => ViolationPolicy::parse(value).unwrap_or_else(|| {
+                println!("Unknown position violation policy {}. Using drop.", value);
+                ViolationPolicy::Drop
+            }),
+            None => ViolationPolicy::Drop,
+        };
+
+        Self {
+            position_policy,
+            max_timestamp_drift: config.get_int(
+                "Security",
+                "MaxTimestampDrift",
+                DEFAULT_MAX_TIMESTAMP_DRIFT,
+            ),
+            speed_tolerance: config.get_int("Security", "SpeedTolerance", DEFAULT_SPEED_TOLERANCE),
+        }
+    }
+}
+
+// Reasons a position packet is rejected.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PositionViolation {
+    Checksum,
+    Timestamp,
+    Speed,
+    Energy,
+}